@@ -107,6 +107,72 @@ pub async fn export_bundle_command(folder_path: Option<String>) {
     std::process::exit(0);
 }
 
+/// Actions of the migrate command.
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum MigrateAction {
+    /// Applies the pending migrations.
+    Up,
+    /// Reverts the applied migrations down to the target version.
+    Down,
+    /// Lists the migrations and whether they are applied.
+    Status,
+}
+
+/// Applies, reverts or lists the SQL migrations of a database.
+pub async fn migrate_command(
+    database_type: String,
+    url: String,
+    action: MigrateAction,
+    source: String,
+    target: Option<i64>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use crate::components::database::{Database, DatabaseOptions, migrate};
+
+    let options = DatabaseOptions {
+        max_connections: Some(1),
+        ..Default::default()
+    };
+    let database = Database::connect(&database_type, &url, options).await?;
+    let db = database.connection()?;
+
+    match action {
+        MigrateAction::Up => {
+            let applied = migrate::up(db, &source).await?;
+            if applied.is_empty() {
+                println!("No pending migrations");
+            }
+            for migration in applied {
+                println!("Applied {}/{}", migration.version, migration.description);
+            }
+        }
+        MigrateAction::Down => {
+            let reverted = migrate::down(db, &source, target).await?;
+            if reverted.is_empty() {
+                println!("No migrations to revert");
+            }
+            for migration in reverted {
+                println!("Reverted {}/{}", migration.version, migration.description);
+            }
+        }
+        MigrateAction::Status => {
+            for migration in migrate::status(db, &source).await? {
+                println!(
+                    "{}/{} {}",
+                    migration.version,
+                    migration.description,
+                    if migration.applied {
+                        "(applied)"
+                    } else {
+                        "(pending)"
+                    }
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
 /// Upgrades to the latest version.
 pub async fn upgrade_command(user_agent: Option<String>) -> Result<(), Box<dyn std::error::Error>> {
     let user_agent = user_agent.unwrap_or(
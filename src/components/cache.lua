@@ -0,0 +1,32 @@
+---@meta
+
+---@class CacheOptions
+---@field max_entries number|nil The number of entries kept before the least recently used ones are evicted, 1000 by default
+---@field ttl number|nil The default time to live of the entries in milliseconds, they do not expire by default
+
+---@class CacheStats
+---@field hits number
+---@field misses number
+---@field evictions number
+---@field entries number
+---@field max_entries number
+
+--- An in memory cache shared by every task
+---@class Cache
+---@field get fun(cache: Cache, key: string): any Returns a copy of the value, or nil when the key is missing or expired
+---@field set fun(cache: Cache, key: string, value: any, ttl: number|nil) Stores a copy of a JSON serializable value, which expires after the TTL in milliseconds if given. Storing nil removes the key
+---@field delete fun(cache: Cache, key: string): boolean Removes the key and returns whether it existed
+---@field get_or_set fun(cache: Cache, key: string, callback: fun(): any, ttl: number|nil): any Returns the value of the key, or stores and returns the result of the callback. Concurrent calls for the same key run the callback once
+---@field clear fun(cache: Cache) Removes every entry
+---@field stats fun(cache: Cache): CacheStats
+
+Astra.cache = {}
+
+---Creates a new cache.
+---@param options CacheOptions|nil
+---@return Cache
+---@nodiscard
+function Astra.cache.new(options)
+	---@diagnostic disable-next-line: undefined-global
+	return astra_internal__cache_new(options)
+end
@@ -0,0 +1,364 @@
+use mlua::{LuaSerdeExt, UserData};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    value: serde_json::Value,
+    expires_at: Option<Instant>,
+    /// The position in the recently used order
+    used_at: u64,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    entries: HashMap<String, CacheEntry>,
+    /// The keys ordered from the least to the most recently used
+    order: BTreeMap<u64, String>,
+    clock: u64,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+impl CacheState {
+    fn remove(&mut self, key: &str) -> Option<CacheEntry> {
+        let entry = self.entries.remove(key)?;
+        self.order.remove(&entry.used_at);
+        Some(entry)
+    }
+
+    /// Looks up the key and marks it as the most recently used, without counting the access.
+    fn lookup(&mut self, key: &str) -> Option<serde_json::Value> {
+        let entry = self.entries.get(key)?;
+        if entry
+            .expires_at
+            .is_some_and(|expires_at| expires_at <= Instant::now())
+        {
+            self.remove(key);
+            return None;
+        }
+
+        self.clock += 1;
+        let clock = self.clock;
+        let entry = self.entries.get_mut(key)?;
+        self.order.remove(&entry.used_at);
+        self.order.insert(clock, key.to_string());
+        entry.used_at = clock;
+
+        Some(entry.value.clone())
+    }
+
+    fn get(&mut self, key: &str) -> Option<serde_json::Value> {
+        let value = self.lookup(key);
+        match value {
+            Some(_) => self.hits += 1,
+            None => self.misses += 1,
+        }
+
+        value
+    }
+
+    /// Counts only the hits, for the lookups which are retried on a miss.
+    fn get_hit(&mut self, key: &str) -> Option<serde_json::Value> {
+        let value = self.lookup(key);
+        if value.is_some() {
+            self.hits += 1;
+        }
+
+        value
+    }
+
+    fn set(
+        &mut self,
+        key: String,
+        value: serde_json::Value,
+        ttl: Option<Duration>,
+        max_entries: usize,
+    ) {
+        self.remove(&key);
+        while self.entries.len() >= max_entries {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+            self.evictions += 1;
+        }
+
+        self.clock += 1;
+        self.order.insert(self.clock, key.clone());
+        self.entries.insert(
+            key,
+            CacheEntry {
+                value,
+                expires_at: ttl.map(|ttl| Instant::now() + ttl),
+                used_at: self.clock,
+            },
+        );
+    }
+}
+
+/// An in memory cache shared by every task, which evicts the least recently used entries
+/// once it is full. The values are kept serialized, so they are copies of the Lua values.
+#[derive(Debug, Clone)]
+pub struct LuaCache {
+    state: Arc<Mutex<CacheState>>,
+    /// The keys being computed by `get_or_set`, so that concurrent misses compute them once
+    pending: Arc<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>>,
+    max_entries: usize,
+    ttl: Option<Duration>,
+}
+impl LuaCache {
+    pub fn register_to_lua(lua: &mlua::Lua) -> mlua::Result<&'static str> {
+        let function = lua.create_function(|_, options: Option<mlua::Table>| {
+            let (max_entries, ttl) = match options {
+                Some(options) => (
+                    options.get::<Option<usize>>("max_entries")?,
+                    options.get::<Option<u64>>("ttl")?,
+                ),
+                None => (None, None),
+            };
+
+            Ok(Self {
+                state: Arc::new(Mutex::new(CacheState::default())),
+                pending: Arc::new(Mutex::new(HashMap::new())),
+                max_entries: max_entries.unwrap_or(1000).max(1),
+                ttl: ttl.map(Duration::from_millis),
+            })
+        })?;
+        lua.globals().set("astra_internal__cache_new", function)?;
+
+        Ok(include_str!("cache.lua"))
+    }
+
+    fn state(&self) -> mlua::Result<std::sync::MutexGuard<'_, CacheState>> {
+        self.state
+            .lock()
+            .map_err(|e| mlua::Error::runtime(format!("The cache is poisoned: {e}")))
+    }
+
+    fn get(&self, lua: &mlua::Lua, key: &str) -> mlua::Result<Option<mlua::Value>> {
+        match self.state()?.get(key) {
+            Some(value) => Ok(Some(lua.to_value(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn set(
+        &self,
+        lua: &mlua::Lua,
+        key: String,
+        value: &mlua::Value,
+        ttl: Option<u64>,
+    ) -> mlua::Result<()> {
+        if value.is_nil() {
+            self.state()?.remove(&key);
+            return Ok(());
+        }
+
+        let value = lua.from_value::<serde_json::Value>(value.clone())?;
+        let ttl = ttl.map(Duration::from_millis).or(self.ttl);
+        self.state()?.set(key, value, ttl, self.max_entries);
+
+        Ok(())
+    }
+}
+impl UserData for LuaCache {
+    fn add_methods<M: mlua::UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("get", |lua, this, key: String| this.get(lua, &key));
+
+        methods.add_method(
+            "set",
+            |lua, this, (key, value, ttl): (String, mlua::Value, Option<u64>)| {
+                this.set(lua, key, &value, ttl)
+            },
+        );
+
+        methods.add_method("delete", |_, this, key: String| {
+            Ok(this.state()?.remove(&key).is_some())
+        });
+
+        // the userdata stays borrowed through an async method, which would keep concurrent
+        // callers waiting on the same key from borrowing it
+        methods.add_async_function(
+            "get_or_set",
+            |lua,
+             (cache, key, callback, ttl): (
+                mlua::UserDataRef<Self>,
+                String,
+                mlua::Function,
+                Option<u64>,
+            )| async move {
+                let this = cache.clone();
+                drop(cache);
+
+                if let Some(value) = this.state()?.get_hit(&key) {
+                    return lua.to_value(&value);
+                }
+
+                let lock = this
+                    .pending
+                    .lock()
+                    .map_err(|e| mlua::Error::runtime(format!("The cache is poisoned: {e}")))?
+                    .entry(key.clone())
+                    .or_default()
+                    .clone();
+                let guard = lock.lock().await;
+
+                // another task may have computed the value while this one was waiting
+                let cached = this.state()?.get(&key);
+                let value = match cached {
+                    Some(value) => lua.to_value(&value),
+                    None => match callback.call_async::<mlua::Value>(()).await {
+                        Ok(value) => this.set(&lua, key.clone(), &value, ttl).map(|_| value),
+                        Err(e) => Err(e),
+                    },
+                };
+
+                // the lock is left to the tasks still waiting on it, and the key may already
+                // belong to a newer lock once they are done
+                drop(guard);
+                if let Ok(mut pending) = this.pending.lock()
+                    && let Some(current) = pending.get(&key)
+                    && Arc::ptr_eq(current, &lock)
+                    && Arc::strong_count(&lock) == 2
+                {
+                    pending.remove(&key);
+                }
+
+                value
+            },
+        );
+
+        methods.add_method("clear", |_, this, ()| {
+            let mut state = this.state()?;
+            state.entries.clear();
+            state.order.clear();
+
+            Ok(())
+        });
+
+        methods.add_method("stats", |lua, this, ()| {
+            let state = this.state()?;
+            let stats = lua.create_table()?;
+            stats.set("hits", state.hits)?;
+            stats.set("misses", state.misses)?;
+            stats.set("evictions", state.evictions)?;
+            stats.set("entries", state.entries.len())?;
+            stats.set("max_entries", this.max_entries)?;
+
+            Ok(stats)
+        });
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn cache(lua: &mlua::Lua, options: &str) -> LuaCache {
+        LuaCache::register_to_lua(lua).unwrap();
+        let cache = lua
+            .load(format!("astra_internal__cache_new({options})"))
+            .eval::<mlua::AnyUserData>()
+            .unwrap();
+        lua.globals().set("cache", &cache).unwrap();
+        cache.borrow::<LuaCache>().unwrap().clone()
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used() {
+        let lua = mlua::Lua::new();
+        let cache = cache(&lua, "{ max_entries = 2 }");
+        lua.load(
+            "cache:set('a', 1)
+            cache:set('b', 2)
+            assert(cache:get('a') == 1)
+            cache:set('c', 3)
+            assert(cache:get('b') == nil)
+            assert(cache:get('a') == 1)
+            assert(cache:get('c') == 3)",
+        )
+        .exec()
+        .unwrap();
+
+        let state = cache.state().unwrap();
+        assert_eq!(state.entries.len(), 2);
+        assert_eq!(state.order.len(), 2);
+        assert_eq!(state.evictions, 1);
+    }
+
+    #[test]
+    fn expires_the_entries() {
+        let lua = mlua::Lua::new();
+        cache(&lua, "{ ttl = 20 }");
+        lua.load(
+            "cache:set('default', 1)
+            cache:set('short', 2, 1)
+            cache:set('long', 3, 60000)",
+        )
+        .exec()
+        .unwrap();
+        std::thread::sleep(Duration::from_millis(40));
+        lua.load(
+            "assert(cache:get('default') == nil)
+            assert(cache:get('short') == nil)
+            assert(cache:get('long') == 3)
+            assert(cache:stats().entries == 1)",
+        )
+        .exec()
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn computes_missing_values_once() {
+        let lua = mlua::Lua::new();
+        let cache = cache(&lua, "");
+        lua.globals()
+            .set(
+                "sleep",
+                lua.create_async_function(|_, ms: u64| async move {
+                    tokio::time::sleep(Duration::from_millis(ms)).await;
+                    Ok(())
+                })
+                .unwrap(),
+            )
+            .unwrap();
+        lua.load(
+            "calls = 0
+            function compute()
+                calls = calls + 1
+                sleep(50)
+                if calls == 1 then error('failed') end
+                return calls
+            end",
+        )
+        .exec()
+        .unwrap();
+
+        // the first call fails while the second waits, and the third comes while the second
+        // computes, so it has to wait on the same lock instead of computing again
+        let first = lua
+            .load("return pcall(cache.get_or_set, cache, 'key', compute)")
+            .eval_async::<bool>();
+        let second = lua
+            .load("return cache:get_or_set('key', compute)")
+            .eval_async::<i64>();
+        let third = async {
+            tokio::time::sleep(Duration::from_millis(75)).await;
+            lua.load("return cache:get_or_set('key', compute)")
+                .eval_async::<i64>()
+                .await
+        };
+        let (first, second, third) = tokio::join!(first, second, third);
+
+        assert!(!first.unwrap());
+        assert_eq!(second.unwrap(), 2);
+        assert_eq!(third.unwrap(), 2);
+        assert_eq!(lua.globals().get::<i64>("calls").unwrap(), 2);
+        assert!(cache.pending.lock().unwrap().is_empty());
+    }
+}
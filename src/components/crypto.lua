@@ -2,7 +2,7 @@
 
 Astra.crypto = {}
 
----Hashes a given string according to the provided hash type.
+---Hashes a given string according to the provided hash type. Unknown hash types raise an error.
 ---@param hash_type "sha2_256"|"sha3_256"|"sha2_512"|"sha3_512"
 ---@param input string The input to be hashed
 ---@return string
@@ -11,6 +11,136 @@ function Astra.crypto.hash(hash_type, input)
 	return astra_internal__hash(hash_type, input)
 end
 
+---Signs the input with the key, as HMAC of the provided hash type. Unknown hash types raise an error.
+---@param hash_type "sha2_256"|"sha3_256"|"sha2_512"|"sha3_512"
+---@param key string The secret key
+---@param input string The input to be signed
+---@param format? "hex"|"base64"|"raw" The encoding of the signature, hex by default. Raw returns the bytes as they are
+---@return string
+function Astra.crypto.hmac(hash_type, key, input, format)
+	---@diagnostic disable-next-line: undefined-global
+	return astra_internal__hmac(hash_type, key, input, format)
+end
+
+---Compares two strings in a time that does not depend on where they differ, to compare signatures
+---and tokens without leaking how much of them matched
+---@param a string
+---@param b string
+---@return boolean
+function Astra.crypto.constant_time_eq(a, b)
+	---@diagnostic disable-next-line: undefined-global
+	return astra_internal__constant_time_eq(a, b)
+end
+
+Astra.crypto.password = {}
+
+---@class PasswordHashOptions
+---@field algorithm? "argon2id"|"argon2i"|"argon2d"|"bcrypt" Argon2id by default
+---@field memory_cost? number The memory Argon2 uses in KiB, 19456 by default
+---@field time_cost? number The iterations of Argon2, 2 by default
+---@field parallelism? number The lanes of Argon2, 1 by default
+---@field cost? number The log2 of the bcrypt rounds, 12 by default
+
+---Hashes a password with a random salt into a PHC string, such as `$argon2id$v=19$m=19456,t=2,p=1$...`,
+---which stores the algorithm and its parameters for the verification. bcrypt hashes use their own `$2b$` format.
+---The hashing runs on a separate thread, so it does not block the other requests
+---@param password string
+---@param options? PasswordHashOptions
+---@return string
+function Astra.crypto.password.hash(password, options)
+	---@diagnostic disable-next-line: undefined-global
+	return astra_internal__password_hash(password, options)
+end
+
+---Checks the password against an Argon2 or bcrypt hash, raising an error when the hash is malformed
+---@param password string
+---@param hash string
+---@return boolean
+function Astra.crypto.password.verify(password, hash)
+	---@diagnostic disable-next-line: undefined-global
+	return astra_internal__password_verify(password, hash)
+end
+
+Astra.crypto.jwt = {}
+
+---@alias jwt_algorithm "HS256"|"HS384"|"HS512"|"RS256"|"RS384"|"RS512"|"PS256"|"PS384"|"PS512"|"ES256"|"ES384"|"EdDSA"
+
+---@class JWTSignOptions
+---@field alg? jwt_algorithm HS256 by default
+---@field kid? string The key id added to the header
+
+---@class JWTVerifyOptions
+---@field alg jwt_algorithm The only algorithm accepted, which is required
+---@field leeway? number The seconds of clock difference allowed for `exp` and `nbf`, 60 by default
+---@field audience? string|string[] The accepted audiences, `aud` is not checked otherwise
+---@field issuer? string|string[] The accepted issuers, `iss` is not checked otherwise
+
+---Signs the claims into a token. The key is the secret for the HS algorithms, or a private key in PEM for the others
+---@param claims table
+---@param key string
+---@param options? JWTSignOptions
+---@return string
+function Astra.crypto.jwt.sign(claims, key, options)
+	---@diagnostic disable-next-line: undefined-global
+	return astra_internal__jwt_sign(claims, key, options)
+end
+
+---Verifies the token and returns its claims, raising an error when the signature, `exp`, `nbf`, or the given
+---audience or issuer do not match. The claims must contain `exp`. The key is the secret for the HS algorithms,
+---or a public key in PEM for the others, which the HS algorithms refuse
+---@param token string
+---@param key string
+---@param options JWTVerifyOptions
+---@return table
+function Astra.crypto.jwt.verify(token, key, options)
+	---@diagnostic disable-next-line: undefined-global
+	return astra_internal__jwt_verify(token, key, options)
+end
+
+---Returns the given number of random bytes from the secure generator of the operating system, up to 1 MiB
+---@param length number
+---@return string
+function Astra.crypto.random_bytes(length)
+	---@diagnostic disable-next-line: undefined-global
+	return astra_internal__random_bytes(length)
+end
+
+---Returns a random URL safe token, for session ids, reset links and the like
+---@param length? number The number of random bytes before encoding, 32 by default
+---@return string
+function Astra.crypto.random_token(length)
+	---@diagnostic disable-next-line: undefined-global
+	return astra_internal__random_token(length)
+end
+
+---@class CipherOptions
+---@field algorithm? "aes-256-gcm"|"aes-128-gcm"|"chacha20-poly1305" AES-256-GCM by default
+---Authenticated along with the ciphertext without being encrypted, such as the id of the row. Decrypting needs the same data
+---@field associated_data? string
+---@field format? "base64"|"raw" The encoding of the ciphertext, base64 by default
+
+---Encrypts the plaintext with a random nonce, returning the nonce followed by the ciphertext and its tag.
+---The key is 32 bytes, or 16 for AES-128-GCM, such as one from `Astra.crypto.random_bytes(32)`
+---@param key string
+---@param plaintext string
+---@param options? CipherOptions
+---@return string
+function Astra.crypto.encrypt(key, plaintext, options)
+	---@diagnostic disable-next-line: undefined-global
+	return astra_internal__encrypt(key, plaintext, options)
+end
+
+---Decrypts what `Astra.crypto.encrypt` returned, raising an error when the key, the associated data or the
+---ciphertext do not match
+---@param key string
+---@param ciphertext string
+---@param options? CipherOptions
+---@return string
+function Astra.crypto.decrypt(key, ciphertext, options)
+	---@diagnostic disable-next-line: undefined-global
+	return astra_internal__decrypt(key, ciphertext, options)
+end
+
 Astra.crypto.base64 = {}
 
 ---Encodes the given input as Base64
@@ -0,0 +1,126 @@
+---@meta
+
+--- The outcome of an executed statement
+---@class DatabaseExecuteResult
+---@field rows_affected number Number of rows changed by the statement
+---@field last_insert_id number|nil The id of the last row inserted by the statement, nil when it inserted none, SQLite and MySQL only
+---@field rows table[] Rows returned by a `RETURNING` clause
+
+--- A versioned migration from the migrations folder
+---@class DatabaseMigration
+---@field version number
+---@field description string
+---@field applied boolean
+---@field reversible boolean Whether the migration has a down file
+
+--- A notification received from a Postgres channel
+---@class DatabaseNotification
+---@field channel string
+---@field payload string
+---@field process_id number The process of the server connection that sent the notification
+
+--- A row changed by a committed SQLite transaction
+---@class DatabaseChange
+---@field operation "insert"|"update"|"delete"
+---@field database string
+---@field table string
+---@field rowid number
+
+--- The settings of the connection pool, with the durations in milliseconds
+---@class DatabaseOptions
+---@field max_connections number|nil Defaults to 10
+---@field min_connections number|nil Connections kept open even when idle, defaults to 0
+---@field acquire_timeout number|nil How long to wait for a free connection
+---@field idle_timeout number|nil How long a connection can stay idle before it is closed
+---@field max_lifetime number|nil How long a connection is kept before it is replaced
+---@field after_connect string|nil SQL to run on every new connection
+---@field journal_mode "delete"|"truncate"|"persist"|"memory"|"wal"|"off"|nil SQLite only
+---@field busy_timeout number|nil How long SQLite waits for a locked database, SQLite only
+---@field foreign_keys boolean|nil Enforces the foreign keys, SQLite only
+---@field pragmas table<string, string>|nil Other pragmas to set on each connection, SQLite only
+
+--- The state of the connection pool
+---@class DatabaseStats
+---@field size number Number of open connections
+---@field idle number Number of connections waiting to be used
+---@field in_use number Number of connections currently used
+---@field max_connections number
+
+--- The rows streamed by `query_iter`, which gives the next row on each call and `nil` at the end
+---@class DatabaseRows
+---@overload fun(): table | nil
+---@field close fun(rows: DatabaseRows) Stops reading the rows and gives the connection back to the pool
+
+--- SQL driver
+---@class Database
+---@field execute fun(database: Database, sql: string, parameters: table | nil): DatabaseExecuteResult
+---@field query_one fun(database: Database, sql: string, parameters: table | nil): table | nil
+---@field query_all fun(database: Database, sql: string, parameters: table | nil): table | nil
+---@field query_iter fun(database: Database, sql: string, parameters: table | nil, batch_size: number | nil): DatabaseRows Streams the rows of the query for use in a for loop, holding at most `batch_size` rows (100 by default) in memory
+---@field listen fun(database: Database, channel: string, callback: fun(notification: DatabaseNotification)): TaskHandler Calls the callback for each notification of the Postgres channel
+---@field notify fun(database: Database, channel: string, payload: string|nil) Sends a notification on the Postgres channel
+---@field on_change fun(database: Database, callback: fun(change: DatabaseChange)): TaskHandler Calls the callback for each row changed by a committed SQLite transaction
+---@field table fun(database: Database, name: string): DatabaseTable Starts a query on the table without writing SQL
+---@field prepare fun(database: Database, sql: string): DatabaseStatement Checks the statement with the database and returns a handle to run it again with other parameters
+---@field transaction fun(database: Database, callback: fun(transaction: DatabaseTransaction): ...): ... Runs the callback in a transaction which is committed when it returns and rolled back when it errors
+---@field migrate fun(database: Database, source: string): DatabaseMigration[] Applies the pending migrations of the folder and returns them
+---@field migrate_down fun(database: Database, source: string, target: number|nil): DatabaseMigration[] Reverts the migrations newer than the target version, or only the latest one, and returns them. Raises an error if one of them has no down file
+---@field migration_status fun(database: Database, source: string): DatabaseMigration[] Lists the migrations of the folder and whether they are applied
+---@field stats fun(database: Database): DatabaseStats
+---@field close fun(database: Database)
+
+--- A statement prepared with `Database:prepare`
+---@class DatabaseStatement
+---@field execute fun(statement: DatabaseStatement, parameters: table | nil): DatabaseExecuteResult
+---@field query_one fun(statement: DatabaseStatement, parameters: table | nil): table | nil
+---@field query_all fun(statement: DatabaseStatement, parameters: table | nil): table | nil
+---@field query_iter fun(statement: DatabaseStatement, parameters: table | nil, batch_size: number | nil): DatabaseRows
+
+--- A query on a single table. Every method except the ones running the query returns a new builder.
+---@class DatabaseTable
+---@field where fun(table: DatabaseTable, column: string|table<string, any>, operator: "="|"!="|"<>"|"<"|"<="|">"|">="|"like"|"not like"|"in"|"not in"|nil, value: any): DatabaseTable Adds a condition, either as a table of columns equal to their values or as a column, an operator and a value
+---@field select fun(table: DatabaseTable, columns: string[]): DatabaseTable Picks the columns to return, all of them by default
+---@field order_by fun(table: DatabaseTable, column: string, direction: "asc"|"desc"|nil): DatabaseTable
+---@field limit fun(table: DatabaseTable, limit: number): DatabaseTable
+---@field offset fun(table: DatabaseTable, offset: number): DatabaseTable
+---@field returning fun(table: DatabaseTable, columns: string[]): DatabaseTable Returns these columns of the inserted, updated or deleted rows
+---@field all fun(table: DatabaseTable): table[]
+---@field first fun(table: DatabaseTable): table|nil
+---@field count fun(table: DatabaseTable): number
+---@field insert fun(table: DatabaseTable, values: table<string, any>|table<string, any>[]): DatabaseExecuteResult Inserts a row, or several rows from a list
+---@field update fun(table: DatabaseTable, values: table<string, any>, options: { all: boolean }?): DatabaseExecuteResult Updates the matching rows, which needs a where condition unless `all` is set
+---@field delete fun(table: DatabaseTable, options: { all: boolean }?): DatabaseExecuteResult Deletes the matching rows, which needs a where condition unless `all` is set
+
+--- A transaction running on a single connection of the pool
+---@class DatabaseTransaction
+---@field execute fun(transaction: DatabaseTransaction, sql: string, parameters: table | nil): DatabaseExecuteResult
+---@field query_one fun(transaction: DatabaseTransaction, sql: string, parameters: table | nil): table | nil
+---@field query_all fun(transaction: DatabaseTransaction, sql: string, parameters: table | nil): table | nil
+---@field commit fun(transaction: DatabaseTransaction) Commits the transaction
+---@field rollback fun(transaction: DatabaseTransaction) Rolls back the transaction
+
+---Opens a new SQL connection using the provided URL and returns a table representing the connection.
+---@param database_type "sqlite"|"postgres"|"mysql" The type of database to connect to.
+---@param url string The URL of the SQL database to connect to.
+---@param options DatabaseOptions|number? The settings of the pool, or only the max number of connections
+---@return Database Database that represents the SQL connection.
+---@nodiscard
+function Astra.database_connect(database_type, url, options)
+    ---@diagnostic disable-next-line: undefined-global
+	return astra_internal__database_connect(database_type, url, options)
+end
+
+---Represents an explicit SQL `NULL`. Rows contain it in place of `nil` so the columns are kept,
+---and it can be passed as a parameter. It is the same value as a JSON `null`.
+---@type lightuserdata
+---@diagnostic disable-next-line: undefined-global
+Astra.database_null = astra_internal__database_null
+
+---Marks a string as binary data, so that it is bound as `BLOB` or `BYTEA` instead of text.
+---Strings which are not valid UTF-8 are bound as bytes without it.
+---@param data string The raw bytes
+---@return userdata
+function Astra.database_bytes(data)
+    ---@diagnostic disable-next-line: undefined-global
+	return astra_internal__database_bytes(data)
+end
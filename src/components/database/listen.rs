@@ -0,0 +1,203 @@
+use super::DatabaseType;
+use crate::components::global::TaskHandler;
+use mlua::LuaSerdeExt;
+use sqlx::{
+    Executor, Sqlite,
+    pool::PoolOptions,
+    postgres::PgListener,
+    sqlite::{SqliteConnection, SqliteOperation},
+};
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+/// A row changed by a committed SQLite transaction.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SqliteChange {
+    pub operation: &'static str,
+    pub database: String,
+    pub table: String,
+    pub rowid: i64,
+}
+
+/// A notification sent with `NOTIFY` or `pg_notify` on Postgres.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PostgresNotification {
+    pub channel: String,
+    pub payload: String,
+    pub process_id: u32,
+}
+
+/// Installs the hooks on every new connection of the pool, after running the `after_connect`
+/// SQL. The update hook fires as soon as a row changes, so the changes are held until the
+/// transaction commits and are dropped when it rolls back. Nothing is held while no
+/// `on_change` callback is subscribed.
+pub fn sqlite_change_hooks(
+    options: PoolOptions<Sqlite>,
+    sender: broadcast::Sender<SqliteChange>,
+    after_connect: Option<String>,
+) -> PoolOptions<Sqlite> {
+    options.after_connect(move |connection: &mut SqliteConnection, _| {
+        let sender = sender.clone();
+        let after_connect = after_connect.clone();
+        Box::pin(async move {
+            if let Some(sql) = after_connect {
+                connection.execute(sqlx::raw_sql(&sql)).await?;
+            }
+
+            let mut handle = connection.lock_handle().await?;
+            let pending = Arc::new(Mutex::new(Vec::new()));
+
+            let update_pending = pending.clone();
+            let update_sender = sender.clone();
+            handle.set_update_hook(move |result| {
+                if update_sender.receiver_count() == 0 {
+                    return;
+                }
+
+                let operation = match result.operation {
+                    SqliteOperation::Insert => "insert",
+                    SqliteOperation::Update => "update",
+                    SqliteOperation::Delete => "delete",
+                    SqliteOperation::Unknown(_) => "unknown",
+                };
+
+                if let Ok(mut pending) = update_pending.lock() {
+                    pending.push(SqliteChange {
+                        operation,
+                        database: result.database.to_string(),
+                        table: result.table.to_string(),
+                        rowid: result.rowid,
+                    });
+                }
+            });
+
+            let commit_pending = pending.clone();
+            handle.set_commit_hook(move || {
+                if let Ok(mut pending) = commit_pending.lock() {
+                    for change in pending.drain(..) {
+                        // there is no one to receive the change without a subscriber
+                        let _ = sender.send(change);
+                    }
+                }
+                true
+            });
+
+            handle.set_rollback_hook(move || {
+                if let Ok(mut pending) = pending.lock() {
+                    pending.clear();
+                }
+            });
+
+            Ok(())
+        })
+    })
+}
+
+/// Calls the callback with each committed change until the task is aborted.
+pub fn on_change(
+    lua: mlua::Lua,
+    changes: &broadcast::Sender<SqliteChange>,
+    callback: mlua::Function,
+) -> TaskHandler<()> {
+    let mut receiver = changes.subscribe();
+
+    TaskHandler {
+        handler: Some(tokio::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(change) => {
+                        let result = match lua.to_value(&change) {
+                            Ok(change) => callback.call_async::<()>(change).await,
+                            Err(e) => Err(e),
+                        };
+                        if let Err(e) = result {
+                            tracing::error!("Error running the change callback: {e}");
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::error!(
+                            "Missed {skipped} database changes as the callback fell behind"
+                        );
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        })),
+    }
+}
+
+/// Listens on a Postgres channel on a dedicated connection and calls the callback with each
+/// notification until the task is aborted or the pool is closed.
+pub async fn listen(
+    lua: mlua::Lua,
+    db: &DatabaseType,
+    channel: &str,
+    callback: mlua::Function,
+) -> mlua::Result<TaskHandler<()>> {
+    let DatabaseType::Postgres(pool) = db else {
+        return Err(mlua::Error::runtime(
+            "Listening is only supported by Postgres, use on_change for SQLite",
+        ));
+    };
+
+    let mut listener = match PgListener::connect_with(pool).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            return Err(mlua::Error::runtime(format!(
+                "Error connecting the listener: {e:#?}"
+            )));
+        }
+    };
+    if let Err(e) = listener.listen(channel).await {
+        return Err(mlua::Error::runtime(format!(
+            "Error listening to the channel: {e:#?}"
+        )));
+    }
+
+    Ok(TaskHandler {
+        handler: Some(tokio::spawn(async move {
+            loop {
+                // the listener reconnects by itself and only fails once the pool is closed
+                let notification = match listener.recv().await {
+                    Ok(notification) => PostgresNotification {
+                        channel: notification.channel().to_string(),
+                        payload: notification.payload().to_string(),
+                        process_id: notification.process_id(),
+                    },
+                    Err(e) => {
+                        tracing::error!("Stopped listening to the database: {e}");
+                        break;
+                    }
+                };
+
+                let result = match lua.to_value(&notification) {
+                    Ok(notification) => callback.call_async::<()>(notification).await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = result {
+                    tracing::error!("Error running the notification callback: {e}");
+                }
+            }
+        })),
+    })
+}
+
+pub async fn notify(db: &DatabaseType, channel: &str, payload: &str) -> mlua::Result<()> {
+    let DatabaseType::Postgres(pool) = db else {
+        return Err(mlua::Error::runtime(
+            "Notifying is only supported by Postgres",
+        ));
+    };
+
+    match sqlx::query("SELECT pg_notify($1, $2)")
+        .bind(channel)
+        .bind(payload)
+        .execute(pool)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(mlua::Error::runtime(format!(
+            "Error sending the notification: {e:#?}"
+        ))),
+    }
+}
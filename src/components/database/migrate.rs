@@ -0,0 +1,171 @@
+use super::DatabaseType;
+use sqlx::{
+    Pool,
+    migrate::{Migrate, MigrateError, Migrator},
+};
+
+/// A versioned migration found in the migrations folder. Sqlx keeps track of the
+/// applied ones in the `_sqlx_migrations` table.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+    pub reversible: bool,
+}
+
+async fn applied_versions<DB>(pool: &Pool<DB>) -> Result<Vec<i64>, MigrateError>
+where
+    DB: sqlx::Database,
+    DB::Connection: Migrate,
+{
+    let mut connection = pool.acquire().await?;
+    let mut versions = connection
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|migration| migration.version)
+        .collect::<Vec<_>>();
+    versions.sort();
+
+    Ok(versions)
+}
+
+/// Looks the table up instead of creating it, so that reading the status changes nothing.
+async fn has_migrations_table(db: &DatabaseType) -> Result<bool, MigrateError> {
+    let count: i64 = match db {
+        DatabaseType::Sqlite(pool) => {
+            sqlx::query_scalar(
+                "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations'",
+            )
+            .fetch_one(pool)
+            .await?
+        }
+        DatabaseType::Postgres(pool) => {
+            sqlx::query_scalar("SELECT COUNT(to_regclass('_sqlx_migrations'))")
+                .fetch_one(pool)
+                .await?
+        }
+        DatabaseType::MySql(pool) => {
+            sqlx::query_scalar(
+                "SELECT COUNT(*) FROM information_schema.tables WHERE table_schema = DATABASE() AND table_name = '_sqlx_migrations'",
+            )
+            .fetch_one(pool)
+            .await?
+        }
+    };
+
+    Ok(count > 0)
+}
+
+async fn migrator(source: &str) -> Result<Migrator, MigrateError> {
+    Migrator::new(std::path::PathBuf::from(source)).await
+}
+
+async fn status_with(
+    db: &DatabaseType,
+    migrator: &Migrator,
+) -> Result<Vec<MigrationStatus>, MigrateError> {
+    let applied = match db {
+        _ if !has_migrations_table(db).await? => Vec::new(),
+        DatabaseType::Sqlite(pool) => applied_versions(pool).await?,
+        DatabaseType::Postgres(pool) => applied_versions(pool).await?,
+        DatabaseType::MySql(pool) => applied_versions(pool).await?,
+    };
+
+    Ok(migrator
+        .iter()
+        .filter(|migration| migration.migration_type.is_up_migration())
+        .map(|migration| MigrationStatus {
+            version: migration.version,
+            description: migration.description.to_string(),
+            applied: applied.contains(&migration.version),
+            reversible: migration.migration_type.is_reversible(),
+        })
+        .collect())
+}
+
+/// Lists every migration in the source folder and whether it is applied.
+pub async fn status(db: &DatabaseType, source: &str) -> Result<Vec<MigrationStatus>, MigrateError> {
+    status_with(db, &migrator(source).await?).await
+}
+
+/// Applies the pending migrations and returns the ones that were applied.
+pub async fn up(db: &DatabaseType, source: &str) -> Result<Vec<MigrationStatus>, MigrateError> {
+    let migrator = migrator(source).await?;
+    let before = status_with(db, &migrator).await?;
+
+    match db {
+        DatabaseType::Sqlite(pool) => migrator.run(pool).await?,
+        DatabaseType::Postgres(pool) => migrator.run(pool).await?,
+        DatabaseType::MySql(pool) => migrator.run(pool).await?,
+    };
+
+    Ok(before
+        .into_iter()
+        .filter(|migration| !migration.applied)
+        .map(|migration| MigrationStatus {
+            applied: true,
+            ..migration
+        })
+        .collect())
+}
+
+/// Reverts the applied migrations newer than the target version and returns the ones
+/// that were reverted. Without a target, only the latest applied migration is reverted.
+/// Nothing is reverted when one of them has no down migration, as sqlx would skip it and
+/// leave it applied.
+pub async fn down(
+    db: &DatabaseType,
+    source: &str,
+    target: Option<i64>,
+) -> Result<Vec<MigrationStatus>, MigrateError> {
+    let migrator = migrator(source).await?;
+    let before = status_with(db, &migrator).await?;
+
+    let target = match target {
+        Some(target) => target,
+        None => {
+            let mut applied = before
+                .iter()
+                .filter(|migration| migration.applied)
+                .map(|migration| migration.version)
+                .collect::<Vec<_>>();
+            applied.pop();
+            applied.pop().unwrap_or(i64::MIN)
+        }
+    };
+
+    let irreversible = before
+        .iter()
+        .filter(|migration| {
+            migration.applied && !migration.reversible && migration.version > target
+        })
+        .map(|migration| format!("{}/{}", migration.version, migration.description))
+        .collect::<Vec<_>>();
+    if !irreversible.is_empty() {
+        return Err(MigrateError::Source(
+            format!(
+                "{} can not be reverted without a down migration",
+                irreversible.join(", ")
+            )
+            .into(),
+        ));
+    }
+
+    match db {
+        DatabaseType::Sqlite(pool) => migrator.undo(pool, target).await?,
+        DatabaseType::Postgres(pool) => migrator.undo(pool, target).await?,
+        DatabaseType::MySql(pool) => migrator.undo(pool, target).await?,
+    };
+
+    Ok(before
+        .into_iter()
+        .rev()
+        .filter(|migration| migration.applied && migration.version > target)
+        .map(|migration| MigrationStatus {
+            applied: false,
+            ..migration
+        })
+        .collect())
+}
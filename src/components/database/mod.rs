@@ -0,0 +1,561 @@
+mod listen;
+pub mod migrate;
+mod options;
+mod rows;
+mod statement;
+mod table;
+mod transaction;
+mod types;
+
+use listen::SqliteChange;
+use mlua::{ErrorContext, LuaSerdeExt, UserData};
+pub use options::DatabaseOptions;
+use rows::DatabaseRows;
+use sqlx::{MySql, Pool, Postgres, Sqlite, migrate::MigrateDatabase};
+use statement::{DatabaseStatement, Placeholder, inserts_rows, parse_statement};
+use table::DatabaseTable;
+use transaction::DatabaseTransaction;
+use types::{
+    SqlParameter, bind_mysql, bind_postgres, bind_sqlite, parse_sql_to_lua_mysql,
+    parse_sql_to_lua_postgres, parse_sql_to_lua_sqlite,
+};
+
+#[derive(Debug, Clone)]
+pub enum DatabaseType {
+    Sqlite(Pool<Sqlite>),
+    Postgres(Pool<Postgres>),
+    MySql(Pool<MySql>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Database {
+    pub db: Option<DatabaseType>,
+    /// The committed changes of SQLite, sent by the update hooks of every connection
+    pub changes: Option<tokio::sync::broadcast::Sender<SqliteChange>>,
+}
+impl Database {
+    pub fn register_to_lua(lua: &mlua::Lua) -> mlua::Result<&'static str> {
+        let database_constructor = lua.create_async_function(
+            |lua, (database_type, url, options): (String, String, Option<mlua::Value>)| async move {
+                let options = DatabaseOptions::from_lua(&lua, options)?;
+                Self::connect(&database_type, &url, options).await
+            },
+        )?;
+        lua.globals()
+            .set("astra_internal__database_connect", database_constructor)?;
+        lua.globals()
+            .set("astra_internal__database_null", mlua::Value::NULL)?;
+        lua.globals().set(
+            "astra_internal__database_bytes",
+            lua.create_function(|_, data: mlua::String| {
+                Ok(types::DatabaseBytes(data.as_bytes().to_vec()))
+            })?,
+        )?;
+
+        Ok(include_str!("database.lua"))
+    }
+
+    pub fn connection(&self) -> mlua::Result<&DatabaseType> {
+        self.db
+            .as_ref()
+            .ok_or_else(|| mlua::Error::runtime("The connection is closed"))
+    }
+
+    pub async fn connect(
+        database_type: &str,
+        url: &str,
+        options: DatabaseOptions,
+    ) -> mlua::Result<Database> {
+        // pre checkup
+        if database_type == "sqlite" {
+            match Sqlite::database_exists(url).await {
+                Ok(exists) => {
+                    if !exists {
+                        match Sqlite::create_database(url).await {
+                            Ok(()) => {}
+                            Err(e) => println!("Error creating the Sqlite DB: {e:#?}"),
+                        }
+                    }
+                }
+                Err(e) => println!("Error checking if the Sqlite DB exists: {e:#?}"),
+            }
+        }
+
+        let mut changes = None;
+        let db = match database_type {
+            "sqlite" => {
+                let sender = tokio::sync::broadcast::channel(1024).0;
+                changes = Some(sender.clone());
+
+                match listen::sqlite_change_hooks(
+                    options.pool_options(),
+                    sender,
+                    options.after_connect.clone(),
+                )
+                .connect_with(options.sqlite_connect_options(url)?)
+                .await
+                {
+                    Ok(pool) => Ok(DatabaseType::Sqlite(pool)),
+                    Err(e) => Err(mlua::Error::runtime(format!(
+                        "Error connecting to Sqlite: {e:#?}"
+                    ))),
+                }
+            }
+            "postgres" => match options
+                .with_after_connect(options.pool_options::<Postgres>())
+                .connect(url)
+                .await
+            {
+                Ok(pool) => Ok(DatabaseType::Postgres(pool)),
+                Err(e) => Err(mlua::Error::runtime(format!(
+                    "Error connecting to Postgres: {e:#?}"
+                ))),
+            },
+            "mysql" => match options
+                .with_after_connect(options.pool_options::<MySql>())
+                .connect(url)
+                .await
+            {
+                Ok(pool) => Ok(DatabaseType::MySql(pool)),
+                Err(e) => Err(mlua::Error::runtime(format!(
+                    "Error connecting to MySQL: {e:#?}"
+                ))),
+            },
+            _ => Err(mlua::Error::runtime(
+                "Could not recognize the database type",
+            )),
+        }?;
+
+        Ok(Database {
+            db: Some(db),
+            changes,
+        })
+    }
+}
+
+macro_rules! execute_fn {
+    ($function_name:ident, $database:ty, $bind:ident, $parse_sql:ident, $last_insert_id:expr) => {
+        /// Runs the query and returns the affected rows, the id of the inserted row if the
+        /// database reports one and the rows of a `RETURNING` clause, all from the same statement.
+        async fn $function_name<'e, E>(
+            lua: &mlua::Lua,
+            executor: E,
+            sql: &'e str,
+            parameters: Vec<SqlParameter>,
+        ) -> mlua::Result<mlua::Table>
+        where
+            E: 'e + sqlx::Executor<'e, Database = $database>,
+        {
+            use futures::TryStreamExt;
+
+            let mut rows_affected = 0;
+            let mut last_insert_id = None;
+            let rows = lua.create_table()?;
+
+            // `fetch_many` is the only way to get both the query result and the returned rows
+            // without running the statement twice
+            #[allow(deprecated)]
+            let mut stream = $bind(sql, parameters).fetch_many(executor);
+            loop {
+                match stream.try_next().await {
+                    Ok(Some(sqlx::Either::Left(result))) => {
+                        rows_affected += result.rows_affected();
+                        last_insert_id = $last_insert_id(&result, sql).or(last_insert_id);
+                    }
+                    Ok(Some(sqlx::Either::Right(row))) => rows.push($parse_sql(lua, &row)?)?,
+                    Ok(None) => break,
+                    Err(e) => {
+                        return Err(mlua::Error::runtime(format!(
+                            "Error executing the query: {e:#?}"
+                        )));
+                    }
+                }
+            }
+
+            let result = lua.create_table()?;
+            result.set("rows_affected", rows_affected)?;
+            result.set("last_insert_id", last_insert_id)?;
+            result.set("rows", rows)?;
+
+            Ok(result)
+        }
+    };
+}
+execute_fn!(
+    execute_postgres,
+    Postgres,
+    bind_postgres,
+    parse_sql_to_lua_postgres,
+    |_: &sqlx::postgres::PgQueryResult, _: &str| None::<i64>
+);
+execute_fn!(
+    execute_sqlite,
+    Sqlite,
+    bind_sqlite,
+    parse_sql_to_lua_sqlite,
+    // SQLite keeps the id of the last insert of the connection, even for other statements
+    |result: &sqlx::sqlite::SqliteQueryResult, sql: &str| {
+        Some(result.last_insert_rowid()).filter(|_| result.rows_affected() > 0 && inserts_rows(sql))
+    }
+);
+execute_fn!(
+    execute_mysql,
+    MySql,
+    bind_mysql,
+    parse_sql_to_lua_mysql,
+    // MySQL reports 0 when the statement did not generate an id
+    |result: &sqlx::mysql::MySqlQueryResult, _: &str| {
+        Some(result.last_insert_id()).filter(|id| *id != 0)
+    }
+);
+
+macro_rules! query_iter_fn {
+    ($function_name:ident, $database:ty, $bind:ident, $parse_sql:ident) => {
+        /// Streams the rows of the query from a background task into a bounded channel,
+        /// so only up to `batch_size` rows are held in memory at a time. The returned
+        /// rows give the next row on each call and `nil` once they are exhausted.
+        fn $function_name(
+            lua: &mlua::Lua,
+            pool: Pool<$database>,
+            sql: String,
+            parameters: Vec<SqlParameter>,
+            batch_size: usize,
+        ) -> mlua::Result<DatabaseRows> {
+            use futures::StreamExt;
+
+            let (sender, receiver) = tokio::sync::mpsc::channel(batch_size);
+            let task = tokio::spawn(async move {
+                let mut rows = $bind(&sql, parameters).fetch(&pool);
+                while let Some(row) = rows.next().await {
+                    // the receiver is gone when the iterator is no longer referenced
+                    if sender.send(row).await.is_err() {
+                        break;
+                    }
+                }
+            });
+
+            let receiver = std::sync::Arc::new(tokio::sync::Mutex::new(receiver));
+            let next = lua.create_async_function(move |lua, ()| {
+                let receiver = receiver.clone();
+                async move {
+                    match receiver.lock().await.recv().await {
+                        Some(Ok(row)) => Ok(Some($parse_sql(&lua, &row)?)),
+                        Some(Err(e)) => Err(mlua::Error::runtime(format!(
+                            "Error executing the query: {e:#?}"
+                        ))),
+                        None => Ok(None),
+                    }
+                }
+            })?;
+
+            Ok(DatabaseRows::new(next, task.abort_handle()))
+        }
+    };
+}
+query_iter_fn!(
+    query_iter_postgres,
+    Postgres,
+    bind_postgres,
+    parse_sql_to_lua_postgres
+);
+query_iter_fn!(
+    query_iter_sqlite,
+    Sqlite,
+    bind_sqlite,
+    parse_sql_to_lua_sqlite
+);
+query_iter_fn!(query_iter_mysql, MySql, bind_mysql, parse_sql_to_lua_mysql);
+
+impl DatabaseType {
+    pub fn placeholder(&self) -> Placeholder {
+        match self {
+            DatabaseType::Sqlite(_) | DatabaseType::Postgres(_) => Placeholder::Numbered,
+            DatabaseType::MySql(_) => Placeholder::Question,
+        }
+    }
+
+    pub async fn execute(
+        &self,
+        lua: &mlua::Lua,
+        sql: &str,
+        parameters: Vec<SqlParameter>,
+    ) -> mlua::Result<mlua::Table> {
+        match self {
+            DatabaseType::Sqlite(pool) => execute_sqlite(lua, pool, sql, parameters).await,
+            DatabaseType::Postgres(pool) => execute_postgres(lua, pool, sql, parameters).await,
+            DatabaseType::MySql(pool) => execute_mysql(lua, pool, sql, parameters).await,
+        }
+    }
+
+    pub async fn query_one(
+        &self,
+        lua: &mlua::Lua,
+        sql: &str,
+        parameters: Vec<SqlParameter>,
+    ) -> mlua::Result<mlua::Table> {
+        let result = match self {
+            DatabaseType::Sqlite(pool) => {
+                match bind_sqlite(sql, parameters).fetch_one(pool).await {
+                    Ok(row) => Ok(parse_sql_to_lua_sqlite(lua, &row)?),
+                    Err(e) => Err(e),
+                }
+            }
+            DatabaseType::Postgres(pool) => {
+                match bind_postgres(sql, parameters).fetch_one(pool).await {
+                    Ok(row) => Ok(parse_sql_to_lua_postgres(lua, &row)?),
+                    Err(e) => Err(e),
+                }
+            }
+            DatabaseType::MySql(pool) => match bind_mysql(sql, parameters).fetch_one(pool).await {
+                Ok(row) => Ok(parse_sql_to_lua_mysql(lua, &row)?),
+                Err(e) => Err(e),
+            },
+        };
+
+        result.map_err(|e| mlua::Error::runtime(format!("Error executing the query: {e:#?}")))
+    }
+
+    pub async fn query_all(
+        &self,
+        lua: &mlua::Lua,
+        sql: &str,
+        parameters: Vec<SqlParameter>,
+    ) -> mlua::Result<Vec<mlua::Table>> {
+        let error = |e| mlua::Error::runtime(format!("Error executing the query: {e:#?}"));
+
+        match self {
+            DatabaseType::Sqlite(pool) => bind_sqlite(sql, parameters)
+                .fetch_all(pool)
+                .await
+                .map_err(error)?
+                .iter()
+                .map(|row| parse_sql_to_lua_sqlite(lua, row))
+                .collect(),
+            DatabaseType::Postgres(pool) => bind_postgres(sql, parameters)
+                .fetch_all(pool)
+                .await
+                .map_err(error)?
+                .iter()
+                .map(|row| parse_sql_to_lua_postgres(lua, row))
+                .collect(),
+            DatabaseType::MySql(pool) => bind_mysql(sql, parameters)
+                .fetch_all(pool)
+                .await
+                .map_err(error)?
+                .iter()
+                .map(|row| parse_sql_to_lua_mysql(lua, row))
+                .collect(),
+        }
+    }
+
+    pub fn query_iter(
+        &self,
+        lua: &mlua::Lua,
+        sql: String,
+        parameters: Vec<SqlParameter>,
+        batch_size: Option<usize>,
+    ) -> mlua::Result<DatabaseRows> {
+        let batch_size = batch_size.unwrap_or(100).max(1);
+
+        match self {
+            DatabaseType::Sqlite(pool) => {
+                query_iter_sqlite(lua, pool.clone(), sql, parameters, batch_size)
+            }
+            DatabaseType::Postgres(pool) => {
+                query_iter_postgres(lua, pool.clone(), sql, parameters, batch_size)
+            }
+            DatabaseType::MySql(pool) => {
+                query_iter_mysql(lua, pool.clone(), sql, parameters, batch_size)
+            }
+        }
+    }
+}
+
+impl UserData for Database {
+    fn add_methods<M: mlua::UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_async_method(
+            "execute",
+            |lua, this, (sql, parameters): (String, Option<mlua::Table>)| async move {
+                let db = this.connection()?;
+                let (sql, parameters) = parse_statement(&lua, &sql, parameters, db.placeholder())?;
+                db.execute(&lua, &sql, parameters).await
+            },
+        );
+
+        methods.add_async_method(
+            "query_one",
+            |lua, this, (sql, parameters): (String, Option<mlua::Table>)| async move {
+                let db = this.connection()?;
+                let (sql, parameters) = parse_statement(&lua, &sql, parameters, db.placeholder())?;
+                db.query_one(&lua, &sql, parameters).await
+            },
+        );
+
+        methods.add_async_method(
+            "query_all",
+            |lua, this, (sql, parameters): (String, Option<mlua::Table>)| async move {
+                let db = this.connection()?;
+                let (sql, parameters) = parse_statement(&lua, &sql, parameters, db.placeholder())?;
+                db.query_all(&lua, &sql, parameters).await
+            },
+        );
+
+        methods.add_method(
+            "query_iter",
+            |lua, this, (sql, parameters, batch_size): (String, Option<mlua::Table>, Option<usize>)| {
+                let db = this.connection()?;
+                let (sql, parameters) = parse_statement(lua, &sql, parameters, db.placeholder())?;
+                db.query_iter(lua, sql.into_owned(), parameters, batch_size)?
+                    .into_lua_iterator(lua)
+            },
+        );
+
+        methods.add_async_method(
+            "listen",
+            |lua, this, (channel, callback): (String, mlua::Function)| async move {
+                listen::listen(lua, this.connection()?, &channel, callback).await
+            },
+        );
+
+        methods.add_async_method(
+            "notify",
+            |_, this, (channel, payload): (String, Option<String>)| async move {
+                listen::notify(this.connection()?, &channel, &payload.unwrap_or_default()).await
+            },
+        );
+
+        methods.add_method("on_change", |lua, this, callback: mlua::Function| {
+            this.connection()?;
+            match &this.changes {
+                Some(changes) => Ok(listen::on_change(lua.clone(), changes, callback)),
+                None => Err(mlua::Error::runtime(
+                    "Change callbacks are only supported by SQLite, use listen for Postgres",
+                )),
+            }
+        });
+
+        methods.add_method("stats", |lua, this, ()| {
+            let (size, idle, max_connections) = match this.connection()? {
+                DatabaseType::Sqlite(pool) => (
+                    pool.size(),
+                    pool.num_idle(),
+                    pool.options().get_max_connections(),
+                ),
+                DatabaseType::Postgres(pool) => (
+                    pool.size(),
+                    pool.num_idle(),
+                    pool.options().get_max_connections(),
+                ),
+                DatabaseType::MySql(pool) => (
+                    pool.size(),
+                    pool.num_idle(),
+                    pool.options().get_max_connections(),
+                ),
+            };
+
+            let stats = lua.create_table()?;
+            stats.set("size", size)?;
+            stats.set("idle", idle)?;
+            stats.set("in_use", (size as usize).saturating_sub(idle))?;
+            stats.set("max_connections", max_connections)?;
+
+            Ok(stats)
+        });
+
+        methods.add_method("table", |_, this, table: String| {
+            Ok(DatabaseTable::new(this.connection()?.clone(), table))
+        });
+
+        methods.add_async_method("prepare", |_, this, sql: String| async move {
+            DatabaseStatement::prepare(this.connection()?.clone(), sql).await
+        });
+
+        methods.add_async_method(
+            "transaction",
+            |_, this, callback: mlua::Function| async move {
+                let transaction = match &this.db {
+                    Some(db) => DatabaseTransaction::begin(db).await?,
+                    None => return Err(mlua::Error::runtime("The connection is closed")),
+                };
+
+                match callback
+                    .call_async::<mlua::MultiValue>(transaction.clone())
+                    .await
+                {
+                    Ok(result) => {
+                        // the callback may have already committed or rolled back by itself
+                        if transaction.is_open().await {
+                            transaction.commit().await?;
+                        }
+
+                        Ok(result)
+                    }
+                    // a failed rollback is only attached, as the error of the callback is the
+                    // one that explains what went wrong
+                    Err(e) => {
+                        if transaction.is_open().await
+                            && let Err(rollback_error) = transaction.rollback().await
+                        {
+                            return Err(e.context(rollback_error));
+                        }
+
+                        Err(e)
+                    }
+                }
+            },
+        );
+
+        methods.add_async_method("migrate", |lua, this, source: String| async move {
+            match &this.db {
+                Some(db) => match migrate::up(db, &source).await {
+                    Ok(applied) => lua.to_value(&applied),
+                    Err(e) => Err(mlua::Error::runtime(format!(
+                        "Error running the migrations: {e}"
+                    ))),
+                },
+                None => Err(mlua::Error::runtime("The connection is closed")),
+            }
+        });
+
+        methods.add_async_method(
+            "migrate_down",
+            |lua, this, (source, target): (String, Option<i64>)| async move {
+                match &this.db {
+                    Some(db) => match migrate::down(db, &source, target).await {
+                        Ok(reverted) => lua.to_value(&reverted),
+                        Err(e) => Err(mlua::Error::runtime(format!(
+                            "Error reverting the migrations: {e}"
+                        ))),
+                    },
+                    None => Err(mlua::Error::runtime("The connection is closed")),
+                }
+            },
+        );
+
+        methods.add_async_method("migration_status", |lua, this, source: String| async move {
+            match &this.db {
+                Some(db) => match migrate::status(db, &source).await {
+                    Ok(status) => lua.to_value(&status),
+                    Err(e) => Err(mlua::Error::runtime(format!(
+                        "Error reading the migrations: {e}"
+                    ))),
+                },
+                None => Err(mlua::Error::runtime("The connection is closed")),
+            }
+        });
+
+        methods.add_async_method_mut("close", |_, mut this, _: ()| async move {
+            if let Some(db) = &this.db {
+                match db {
+                    DatabaseType::Sqlite(pool) => pool.close().await,
+                    DatabaseType::Postgres(pool) => pool.close().await,
+                    DatabaseType::MySql(pool) => pool.close().await,
+                };
+            }
+            this.db = None;
+            this.changes = None;
+
+            Ok(())
+        });
+    }
+}
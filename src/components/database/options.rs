@@ -0,0 +1,109 @@
+use mlua::FromLua;
+use sqlx::{
+    Executor,
+    pool::PoolOptions,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode},
+};
+use std::{collections::HashMap, str::FromStr, time::Duration};
+
+/// The settings of the connection pool, with the durations in milliseconds.
+#[derive(Debug, Clone, Default)]
+pub struct DatabaseOptions {
+    pub max_connections: Option<u32>,
+    pub min_connections: Option<u32>,
+    pub acquire_timeout: Option<u64>,
+    pub idle_timeout: Option<u64>,
+    pub max_lifetime: Option<u64>,
+    /// SQL that runs on every new connection of the pool
+    pub after_connect: Option<String>,
+    pub journal_mode: Option<String>,
+    pub busy_timeout: Option<u64>,
+    pub foreign_keys: Option<bool>,
+    pub pragmas: HashMap<String, String>,
+}
+impl DatabaseOptions {
+    /// Reads either the options table or only the max connections as a number.
+    pub fn from_lua(lua: &mlua::Lua, options: Option<mlua::Value>) -> mlua::Result<Self> {
+        match options {
+            None | Some(mlua::Value::Nil) => Ok(Self::default()),
+            Some(mlua::Value::Table(table)) => Ok(Self {
+                max_connections: table.get::<Option<u32>>("max_connections")?,
+                min_connections: table.get::<Option<u32>>("min_connections")?,
+                acquire_timeout: table.get::<Option<u64>>("acquire_timeout")?,
+                idle_timeout: table.get::<Option<u64>>("idle_timeout")?,
+                max_lifetime: table.get::<Option<u64>>("max_lifetime")?,
+                after_connect: table.get::<Option<String>>("after_connect")?,
+                journal_mode: table.get::<Option<String>>("journal_mode")?,
+                busy_timeout: table.get::<Option<u64>>("busy_timeout")?,
+                foreign_keys: table.get::<Option<bool>>("foreign_keys")?,
+                pragmas: table
+                    .get::<Option<HashMap<String, String>>>("pragmas")?
+                    .unwrap_or_default(),
+            }),
+            Some(value) => Ok(Self {
+                max_connections: Some(u32::from_lua(value, lua)?),
+                ..Default::default()
+            }),
+        }
+    }
+
+    pub fn pool_options<DB: sqlx::Database>(&self) -> PoolOptions<DB> {
+        let mut options = PoolOptions::<DB>::new()
+            .max_connections(self.max_connections.unwrap_or(10))
+            .min_connections(self.min_connections.unwrap_or(0));
+
+        if let Some(acquire_timeout) = self.acquire_timeout {
+            options = options.acquire_timeout(Duration::from_millis(acquire_timeout));
+        }
+        if let Some(idle_timeout) = self.idle_timeout {
+            options = options.idle_timeout(Duration::from_millis(idle_timeout));
+        }
+        if let Some(max_lifetime) = self.max_lifetime {
+            options = options.max_lifetime(Duration::from_millis(max_lifetime));
+        }
+
+        options
+    }
+
+    /// Runs the `after_connect` SQL on every new connection. SQLite runs it along with its
+    /// change hooks instead, as a pool only keeps a single callback.
+    pub fn with_after_connect<DB>(&self, options: PoolOptions<DB>) -> PoolOptions<DB>
+    where
+        DB: sqlx::Database,
+        for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    {
+        match self.after_connect.clone() {
+            Some(sql) => options.after_connect(move |connection, _| {
+                let sql = sql.clone();
+                Box::pin(async move {
+                    connection.execute(sqlx::raw_sql(&sql)).await?;
+                    Ok(())
+                })
+            }),
+            None => options,
+        }
+    }
+
+    pub fn sqlite_connect_options(&self, url: &str) -> mlua::Result<SqliteConnectOptions> {
+        let mut options = SqliteConnectOptions::from_str(&format!("sqlite:{url}"))
+            .map_err(|e| mlua::Error::runtime(format!("Invalid Sqlite URL: {e}")))?;
+
+        if let Some(journal_mode) = &self.journal_mode {
+            let journal_mode = SqliteJournalMode::from_str(journal_mode).map_err(|e| {
+                mlua::Error::runtime(format!("Invalid journal mode {journal_mode}: {e}"))
+            })?;
+            options = options.journal_mode(journal_mode);
+        }
+        if let Some(busy_timeout) = self.busy_timeout {
+            options = options.busy_timeout(Duration::from_millis(busy_timeout));
+        }
+        if let Some(foreign_keys) = self.foreign_keys {
+            options = options.foreign_keys(foreign_keys);
+        }
+        for (key, value) in &self.pragmas {
+            options = options.pragma(key.clone(), value.clone());
+        }
+
+        Ok(options)
+    }
+}
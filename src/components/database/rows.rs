@@ -0,0 +1,137 @@
+use mlua::UserData;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::task::AbortHandle;
+
+/// The rows streamed by `query_iter`. Calling it gives the next row, and closing it stops the
+/// background task, which gives its connection back to the pool. It is closed when a Lua 5.4
+/// for loop exits early, on `close()`, and when it is garbage collected.
+pub struct DatabaseRows {
+    next: mlua::Function,
+    task: AbortHandle,
+    closed: AtomicBool,
+}
+
+impl DatabaseRows {
+    pub fn new(next: mlua::Function, task: AbortHandle) -> Self {
+        Self {
+            next,
+            task,
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the iterator along with itself as the to-be-closed value of a generic for loop.
+    pub fn into_lua_iterator(self, lua: &mlua::Lua) -> mlua::Result<mlua::MultiValue> {
+        let rows = lua.create_userdata(self)?;
+        Ok(mlua::MultiValue::from_iter([
+            mlua::Value::UserData(rows.clone()),
+            mlua::Value::Nil,
+            mlua::Value::Nil,
+            mlua::Value::UserData(rows),
+        ]))
+    }
+
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.task.abort();
+    }
+}
+
+impl Drop for DatabaseRows {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl UserData for DatabaseRows {
+    fn add_methods<M: mlua::UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_async_meta_method(
+            mlua::MetaMethod::Call,
+            |_, this, _: mlua::MultiValue| async move {
+                // the rows buffered before closing are dropped along with the rest
+                if this.closed.load(Ordering::Acquire) {
+                    return Ok(mlua::Value::Nil);
+                }
+                this.next.call_async::<mlua::Value>(()).await
+            },
+        );
+
+        methods.add_method("close", |_, this, ()| {
+            this.close();
+            Ok(())
+        });
+
+        #[cfg(feature = "lua54")]
+        methods.add_meta_method(mlua::MetaMethod::Close, |_, this, _: mlua::MultiValue| {
+            this.close();
+            Ok(())
+        });
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::super::{Database, DatabaseOptions};
+    use std::time::Duration;
+
+    // a single connection, so the next query waits for the iterator to give it back
+    async fn lua_with_database() -> mlua::Lua {
+        let options = DatabaseOptions {
+            max_connections: Some(1),
+            acquire_timeout: Some(2000),
+            ..Default::default()
+        };
+        let db = Database::connect("sqlite", "sqlite::memory:", options)
+            .await
+            .unwrap();
+        let lua = mlua::Lua::new();
+        lua.globals().set("db", db).unwrap();
+        lua.load(
+            "db:execute('CREATE TABLE numbers (n INTEGER)')
+            for n = 1, 500 do db:execute('INSERT INTO numbers (n) VALUES ($1)', { n }) end",
+        )
+        .exec_async()
+        .await
+        .unwrap();
+        lua
+    }
+
+    async fn count_after(lua: &mlua::Lua, source: &str) -> i64 {
+        let run = lua.load(source).eval_async::<i64>();
+        tokio::time::timeout(Duration::from_secs(5), run)
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    #[cfg(feature = "lua54")]
+    #[tokio::test]
+    async fn releases_the_connection_when_the_loop_breaks() {
+        let lua = lua_with_database().await;
+        let count = count_after(
+            &lua,
+            "for row in db:query_iter('SELECT n FROM numbers', nil, 10) do
+                if row.n == 3 then break end
+            end
+            return db:query_one('SELECT COUNT(*) AS count FROM numbers').count",
+        )
+        .await;
+        assert_eq!(count, 500);
+    }
+
+    #[tokio::test]
+    async fn releases_the_connection_when_closed() {
+        let lua = lua_with_database().await;
+        let count = count_after(
+            &lua,
+            "local rows = db:query_iter('SELECT n FROM numbers', nil, 10)
+            assert(rows().n == 1)
+            rows:close()
+            assert(rows() == nil)
+            return db:query_one('SELECT COUNT(*) AS count FROM numbers').count",
+        )
+        .await;
+        assert_eq!(count, 500);
+    }
+}
@@ -0,0 +1,612 @@
+use super::{
+    DatabaseType,
+    types::{SqlParameter, parse_parameters},
+};
+use mlua::UserData;
+use std::borrow::Cow;
+
+/// How the database expects the positional parameters to be written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placeholder {
+    /// `$1`, `$2`, ... as used by Postgres and SQLite
+    Numbered,
+    /// `?` for every parameter in order, as used by MySQL
+    Question,
+}
+
+/// A statement with its `:name` and `$name` placeholders replaced by positional ones,
+/// along with the name bound to each position.
+#[derive(Debug, Clone)]
+pub struct NamedStatement {
+    pub sql: String,
+    pub names: Vec<String>,
+}
+impl NamedStatement {
+    pub fn parse(sql: &str, placeholder: Placeholder) -> Self {
+        let bytes = sql.as_bytes();
+        let mut output = String::with_capacity(sql.len());
+        let mut names: Vec<String> = Vec::new();
+
+        // only ASCII delimiters are matched, so every slice falls on a character boundary
+        let mut start = 0;
+        let mut index = 0;
+        while index < bytes.len() {
+            let next = bytes.get(index + 1).copied().unwrap_or_default();
+
+            // strings, quoted identifiers and comments are kept as they are
+            if let Some(end) = skip_literal(sql, index) {
+                index = end;
+                continue;
+            }
+
+            let skip_to = match (bytes[index], next) {
+                // Postgres casts such as `value::text`
+                (b':', b':') => index + 2,
+                (b':' | b'$', next) if next.is_ascii_alphabetic() || next == b'_' => {
+                    let end = sql[index + 1..]
+                        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                        .map_or(bytes.len(), |end| index + 1 + end);
+
+                    // Postgres dollar quoted strings such as `$body$ ... $body$`
+                    if bytes[index] == b'$' && bytes.get(end) == Some(&b'$') {
+                        let tag = &sql[index..=end];
+                        sql[end + 1..]
+                            .find(tag)
+                            .map_or(bytes.len(), |close| end + 1 + close + tag.len())
+                    } else {
+                        let name = &sql[index + 1..end];
+                        output.push_str(&sql[start..index]);
+                        match placeholder {
+                            Placeholder::Numbered => {
+                                let position = match names.iter().position(|known| known == name) {
+                                    Some(position) => position,
+                                    None => {
+                                        names.push(name.to_string());
+                                        names.len() - 1
+                                    }
+                                };
+                                output.push_str(&format!("${}", position + 1));
+                            }
+                            Placeholder::Question => {
+                                names.push(name.to_string());
+                                output.push('?');
+                            }
+                        }
+                        start = end;
+                        end
+                    }
+                }
+                (b'$', b'$') => sql[index + 2..]
+                    .find("$$")
+                    .map_or(bytes.len(), |end| index + end + 4),
+                _ => index + 1,
+            };
+
+            index = skip_to.min(bytes.len());
+        }
+        output.push_str(&sql[start..]);
+
+        Self { sql: output, names }
+    }
+
+    /// Takes the value of every name from the table, in the order of the placeholders.
+    pub fn parameters(
+        &self,
+        lua: &mlua::Lua,
+        parameters: &mlua::Table,
+    ) -> mlua::Result<Vec<SqlParameter>> {
+        self.names
+            .iter()
+            .map(|name| match parameters.get::<mlua::Value>(name.as_str())? {
+                mlua::Value::Nil => Err(mlua::Error::runtime(format!(
+                    "Missing the named parameter {name}, use Astra.database_null for NULL"
+                ))),
+                value => SqlParameter::from_lua(lua, value),
+            })
+            .collect()
+    }
+}
+
+/// Where the string, quoted identifier or comment starting at the index ends, as the
+/// placeholders and keywords within them are only text.
+fn skip_literal(sql: &str, index: usize) -> Option<usize> {
+    let bytes = sql.as_bytes();
+    let next = bytes.get(index + 1).copied().unwrap_or_default();
+
+    let end = match (bytes[index], next) {
+        (quote @ (b'\'' | b'"' | b'`'), _) => sql[index + 1..]
+            .find(quote as char)
+            .map_or(bytes.len(), |end| index + end + 2),
+        (b'-', b'-') => sql[index..]
+            .find('\n')
+            .map_or(bytes.len(), |end| index + end + 1),
+        (b'/', b'*') => sql[index + 2..]
+            .find("*/")
+            .map_or(bytes.len(), |end| index + end + 4),
+        _ => return None,
+    };
+
+    Some(end.min(bytes.len()))
+}
+
+/// At most how many positional parameters the statement takes, from its highest `$N` or `?N`
+/// and its number of other placeholders, such as `?` or the `:name` which SQLite also binds by
+/// position, so that a sparse table of parameters can not ask for more.
+pub fn positional_count(sql: &str) -> usize {
+    let bytes = sql.as_bytes();
+    let mut highest = 0;
+    let mut others = 0;
+
+    let mut index = 0;
+    while index < bytes.len() {
+        if let Some(end) = skip_literal(sql, index) {
+            index = end;
+            continue;
+        }
+        let next = bytes.get(index + 1).copied().unwrap_or_default();
+
+        index = match (bytes[index], next) {
+            // Postgres casts such as `value::text`
+            (b':', b':') => index + 2,
+            (b'$' | b'?', next) if next.is_ascii_digit() => {
+                let end = sql[index + 1..]
+                    .find(|c: char| !c.is_ascii_digit())
+                    .map_or(bytes.len(), |end| index + 1 + end);
+                highest = highest.max(sql[index + 1..end].parse().unwrap_or_default());
+                end
+            }
+            (b'?', _) => {
+                others += 1;
+                index + 1
+            }
+            (b':' | b'@' | b'$', next) if next.is_ascii_alphabetic() || next == b'_' => {
+                others += 1;
+                index + 2
+            }
+            _ => index + 1,
+        };
+    }
+
+    highest.max(others)
+}
+
+/// Named parameters are given as a table with string keys, positional ones as a sequence.
+fn is_named(parameters: &mlua::Table) -> mlua::Result<bool> {
+    let mut named = false;
+    let mut positional = false;
+    for pair in parameters.pairs::<mlua::Value, mlua::Value>() {
+        match pair?.0 {
+            mlua::Value::String(_) => named = true,
+            _ => positional = true,
+        }
+    }
+
+    if named && positional {
+        return Err(mlua::Error::runtime(
+            "The parameters can not mix named and positional values",
+        ));
+    }
+
+    Ok(named)
+}
+
+/// Resolves the parameters of a statement, rewriting the SQL when they are named.
+pub fn parse_statement<'a>(
+    lua: &mlua::Lua,
+    sql: &'a str,
+    parameters: Option<mlua::Table>,
+    placeholder: Placeholder,
+) -> mlua::Result<(Cow<'a, str>, Vec<SqlParameter>)> {
+    match parameters {
+        Some(parameters) if is_named(&parameters)? => {
+            let statement = NamedStatement::parse(sql, placeholder);
+            let parameters = statement.parameters(lua, &parameters)?;
+            Ok((Cow::Owned(statement.sql), parameters))
+        }
+        parameters => Ok((
+            Cow::Borrowed(sql),
+            parse_parameters(lua, parameters, positional_count(sql))?,
+        )),
+    }
+}
+
+/// Whether any of the statements inserts rows, as SQLite keeps the id of the last inserted row
+/// on the connection, where an update or a delete would find the one of an earlier insert.
+/// The verb of a statement is its first word, or the first one after its common table
+/// expressions.
+pub fn inserts_rows(sql: &str) -> bool {
+    let bytes = sql.as_bytes();
+    let mut depth = 0_usize;
+    let mut first_word = true;
+    let mut in_with = false;
+
+    let mut index = 0;
+    while index < bytes.len() {
+        let next = bytes.get(index + 1).copied().unwrap_or_default();
+
+        if let Some(end) = skip_literal(sql, index) {
+            index = end;
+            continue;
+        }
+
+        let skip_to = match (bytes[index], next) {
+            (b'(', _) => {
+                depth += 1;
+                index + 1
+            }
+            (b')', _) => {
+                depth = depth.saturating_sub(1);
+                index + 1
+            }
+            (b';', _) if depth == 0 => {
+                first_word = true;
+                in_with = false;
+                index + 1
+            }
+            (start, _) if start.is_ascii_alphabetic() || start == b'_' => {
+                let end = sql[index..]
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .map_or(bytes.len(), |end| index + end);
+
+                if depth == 0 && (first_word || in_with) {
+                    match sql[index..end].to_ascii_uppercase().as_str() {
+                        "INSERT" | "REPLACE" => return true,
+                        "WITH" if first_word => in_with = true,
+                        "SELECT" | "UPDATE" | "DELETE" | "VALUES" => in_with = false,
+                        _ => {}
+                    }
+                    first_word = false;
+                }
+                end
+            }
+            _ => index + 1,
+        };
+
+        index = skip_to.min(bytes.len());
+    }
+
+    false
+}
+
+/// A statement checked by the database ahead of time. The named placeholders are only parsed
+/// once, and each connection keeps the statement prepared after running it the first time.
+#[derive(Debug, Clone)]
+pub struct DatabaseStatement {
+    pub db: DatabaseType,
+    pub sql: String,
+    pub named: NamedStatement,
+    /// The number of positional parameters, counted once along with the names
+    pub positional: usize,
+}
+impl DatabaseStatement {
+    pub async fn prepare(db: DatabaseType, sql: String) -> mlua::Result<Self> {
+        let named = NamedStatement::parse(&sql, db.placeholder());
+        let checked_sql = if named.names.is_empty() {
+            &sql
+        } else {
+            &named.sql
+        };
+
+        let result = match &db {
+            DatabaseType::Sqlite(pool) => {
+                sqlx::Executor::prepare(pool, checked_sql).await.map(|_| ())
+            }
+            // Postgres infers the parameter types while preparing, such as `INT4` for an
+            // integer column, which would not accept the `INT8` bound from Lua later on. The SQL
+            // is checked with a server-side statement instead, leaving the statement cache of the
+            // connection alone so that the first run prepares it with the bound types.
+            DatabaseType::Postgres(pool) => {
+                async {
+                    let mut connection = pool.acquire().await?;
+                    sqlx::Executor::execute(
+                        &mut *connection,
+                        sqlx::raw_sql(&format!("PREPARE astra_prepare_check AS {checked_sql}")),
+                    )
+                    .await?;
+                    sqlx::Executor::execute(
+                        &mut *connection,
+                        sqlx::raw_sql("DEALLOCATE astra_prepare_check"),
+                    )
+                    .await
+                    .map(|_| ())
+                }
+                .await
+            }
+            DatabaseType::MySql(pool) => {
+                sqlx::Executor::prepare(pool, checked_sql).await.map(|_| ())
+            }
+        };
+
+        match result {
+            Ok(()) => Ok(Self {
+                positional: positional_count(&sql),
+                db,
+                sql,
+                named,
+            }),
+            Err(e) => Err(mlua::Error::runtime(format!(
+                "Error preparing the statement: {e:#?}"
+            ))),
+        }
+    }
+
+    fn resolve(
+        &self,
+        lua: &mlua::Lua,
+        parameters: Option<mlua::Table>,
+    ) -> mlua::Result<(&str, Vec<SqlParameter>)> {
+        match parameters {
+            Some(parameters) if is_named(&parameters)? => {
+                Ok((&self.named.sql, self.named.parameters(lua, &parameters)?))
+            }
+            parameters => Ok((
+                &self.sql,
+                parse_parameters(lua, parameters, self.positional)?,
+            )),
+        }
+    }
+}
+impl UserData for DatabaseStatement {
+    fn add_methods<M: mlua::UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_async_method(
+            "execute",
+            |lua, this, parameters: Option<mlua::Table>| async move {
+                let (sql, parameters) = this.resolve(&lua, parameters)?;
+                this.db.execute(&lua, sql, parameters).await
+            },
+        );
+
+        methods.add_async_method(
+            "query_one",
+            |lua, this, parameters: Option<mlua::Table>| async move {
+                let (sql, parameters) = this.resolve(&lua, parameters)?;
+                this.db.query_one(&lua, sql, parameters).await
+            },
+        );
+
+        methods.add_async_method(
+            "query_all",
+            |lua, this, parameters: Option<mlua::Table>| async move {
+                let (sql, parameters) = this.resolve(&lua, parameters)?;
+                this.db.query_all(&lua, sql, parameters).await
+            },
+        );
+
+        methods.add_method(
+            "query_iter",
+            |lua, this, (parameters, batch_size): (Option<mlua::Table>, Option<usize>)| {
+                let (sql, parameters) = this.resolve(lua, parameters)?;
+                this.db
+                    .query_iter(lua, sql.to_string(), parameters, batch_size)?
+                    .into_lua_iterator(lua)
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn numbered(sql: &str) -> (String, Vec<String>) {
+        let statement = NamedStatement::parse(sql, Placeholder::Numbered);
+        (statement.sql, statement.names)
+    }
+
+    #[test]
+    fn rewrites_named_placeholders() {
+        assert_eq!(
+            numbered("SELECT * FROM users WHERE id = :id AND name = $name"),
+            (
+                "SELECT * FROM users WHERE id = $1 AND name = $2".to_string(),
+                vec!["id".to_string(), "name".to_string()]
+            )
+        );
+        assert_eq!(
+            numbered("UPDATE t SET a = :value_1 WHERE b = :_other"),
+            (
+                "UPDATE t SET a = $1 WHERE b = $2".to_string(),
+                vec!["value_1".to_string(), "_other".to_string()]
+            )
+        );
+    }
+
+    #[test]
+    fn reuses_the_position_of_repeated_names() {
+        assert_eq!(
+            numbered("SELECT :a, :b, :a"),
+            (
+                "SELECT $1, $2, $1".to_string(),
+                vec!["a".to_string(), "b".to_string()]
+            )
+        );
+
+        let statement = NamedStatement::parse("SELECT :a, :b, :a", Placeholder::Question);
+        assert_eq!(statement.sql, "SELECT ?, ?, ?");
+        assert_eq!(statement.names, ["a", "b", "a"]);
+    }
+
+    #[test]
+    fn keeps_strings_identifiers_and_comments() {
+        for sql in [
+            "SELECT ':not_a_name', \"col:name\", `other:name`",
+            "SELECT 1 -- :comment\n",
+            "SELECT 1 /* :comment $other */",
+            "SELECT value::text, '{}'::jsonb",
+            "SELECT $1, $2",
+            "SELECT $$ :body $$",
+            "SELECT $tag$ :body $tag$",
+            "SELECT 'unterminated :name",
+            "SELECT 1 /* unterminated :name",
+        ] {
+            assert_eq!(numbered(sql), (sql.to_string(), Vec::new()), "{sql}");
+        }
+
+        assert_eq!(
+            numbered("SELECT ':x' || :y -- :z\n, $q$ :w $q$, :v::int"),
+            (
+                "SELECT ':x' || $1 -- :z\n, $q$ :w $q$, $2::int".to_string(),
+                vec!["y".to_string(), "v".to_string()]
+            )
+        );
+    }
+
+    #[test]
+    fn keeps_non_ascii_text() {
+        assert_eq!(
+            numbered("SELECT 'héllo :in_text', :name || 'ünï', :ok -- ünïcode :x"),
+            (
+                "SELECT 'héllo :in_text', $1 || 'ünï', $2 -- ünïcode :x".to_string(),
+                vec!["name".to_string(), "ok".to_string()]
+            )
+        );
+    }
+
+    #[test]
+    fn finds_the_inserting_statements() {
+        for sql in [
+            "INSERT INTO t VALUES (1)",
+            "  insert or ignore into t values (1)",
+            "REPLACE INTO t VALUES (1)",
+            "-- comment\n/* UPDATE */ INSERT INTO t VALUES (1)",
+            "WITH x AS (SELECT 1) INSERT INTO t SELECT * FROM x",
+            "WITH RECURSIVE x(n) AS (SELECT 1 UNION SELECT n + 1 FROM x) INSERT INTO t SELECT n FROM x",
+            "UPDATE t SET a = 1; INSERT INTO t VALUES (2)",
+        ] {
+            assert!(inserts_rows(sql), "{sql}");
+        }
+
+        for sql in [
+            "UPDATE t SET a = replace(a, 'x', 'y')",
+            "DELETE FROM t WHERE name = 'INSERT'",
+            "SELECT * FROM t",
+            "WITH x AS (INSERT INTO t VALUES (1) RETURNING id) SELECT * FROM x",
+            "UPDATE t SET a = (SELECT 1) -- INSERT\n",
+            "UPDATE \"insert\" SET a = 1",
+            "",
+        ] {
+            assert!(!inserts_rows(sql), "{sql}");
+        }
+    }
+
+    #[test]
+    fn counts_the_positional_placeholders() {
+        for (sql, count) in [
+            ("SELECT 1", 0),
+            ("SELECT $1, $2, $1", 2),
+            ("SELECT $3", 3),
+            ("SELECT ?, ?, ?", 3),
+            ("SELECT ?2, ?", 2),
+            ("SELECT :a, @b, $c", 3),
+            ("SELECT value::text, $1", 1),
+            ("SELECT '$9', \"?\", `?` -- ?\n /* $8 */", 0),
+            ("SELECT $99999999999999999999999", 0),
+        ] {
+            assert_eq!(positional_count(sql), count, "{sql}");
+        }
+    }
+
+    #[test]
+    fn refuses_parameters_beyond_the_placeholders() {
+        let lua = mlua::Lua::new();
+
+        let parameters = lua
+            .load("{ [1000000000] = 1 }")
+            .eval::<mlua::Table>()
+            .unwrap();
+        let error = parse_statement(&lua, "SELECT $1", Some(parameters), Placeholder::Numbered)
+            .unwrap_err();
+        assert!(error.to_string().contains("beyond the 1 placeholders"));
+
+        let parameters = lua.load("{ 1, 2 }").eval::<mlua::Table>().unwrap();
+        assert!(
+            parse_statement(&lua, "SELECT 1", Some(parameters), Placeholder::Numbered).is_err()
+        );
+
+        // the holes up to the placeholders are still bound as NULL
+        let parameters = lua.load("{ [3] = 'c' }").eval::<mlua::Table>().unwrap();
+        let (_, values) = parse_statement(
+            &lua,
+            "SELECT ?, ?, ?",
+            Some(parameters),
+            Placeholder::Question,
+        )
+        .unwrap();
+        assert!(matches!(
+            values.as_slice(),
+            [
+                SqlParameter::Null,
+                SqlParameter::Null,
+                SqlParameter::Text(_)
+            ]
+        ));
+    }
+
+    #[test]
+    fn binds_the_values_by_name() {
+        let lua = mlua::Lua::new();
+        lua.globals().set("null", mlua::Value::NULL).unwrap();
+        let parameters = lua
+            .load("{ name = 'astra', id = 7, flag = null }")
+            .eval::<mlua::Table>()
+            .unwrap();
+
+        let (sql, values) = parse_statement(
+            &lua,
+            "SELECT :id, :name, :flag, :id",
+            Some(parameters),
+            Placeholder::Numbered,
+        )
+        .unwrap();
+        assert_eq!(sql, "SELECT $1, $2, $3, $1");
+        assert!(matches!(
+            values.as_slice(),
+            [
+                SqlParameter::Integer(7),
+                SqlParameter::Text(name),
+                SqlParameter::Null
+            ] if name == "astra"
+        ));
+    }
+
+    #[test]
+    fn refuses_missing_and_mixed_parameters() {
+        let lua = mlua::Lua::new();
+
+        let parameters = lua.load("{ id = 7 }").eval::<mlua::Table>().unwrap();
+        let error = parse_statement(
+            &lua,
+            "SELECT :id, :name",
+            Some(parameters),
+            Placeholder::Numbered,
+        )
+        .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("Missing the named parameter name")
+        );
+
+        let parameters = lua.load("{ 1, id = 7 }").eval::<mlua::Table>().unwrap();
+        let error = parse_statement(&lua, "SELECT :id", Some(parameters), Placeholder::Numbered)
+            .unwrap_err();
+        assert!(error.to_string().contains("can not mix"));
+    }
+
+    #[test]
+    fn leaves_positional_statements_alone() {
+        let lua = mlua::Lua::new();
+        let parameters = lua.load("{ 1, 'two' }").eval::<mlua::Table>().unwrap();
+
+        let (sql, values) = parse_statement(
+            &lua,
+            "SELECT $1, $2, ':kept'",
+            Some(parameters),
+            Placeholder::Numbered,
+        )
+        .unwrap();
+        assert!(matches!(sql, Cow::Borrowed("SELECT $1, $2, ':kept'")));
+        assert_eq!(values.len(), 2);
+    }
+}
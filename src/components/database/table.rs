@@ -0,0 +1,896 @@
+use super::{DatabaseType, execute_sqlite, statement::Placeholder, types::SqlParameter};
+use mlua::UserData;
+use std::collections::BTreeMap;
+
+const OPERATORS: [&str; 11] = [
+    "=", "!=", "<>", "<", "<=", ">", ">=", "LIKE", "NOT LIKE", "IN", "NOT IN",
+];
+
+#[derive(Debug, Clone)]
+enum ConditionValue {
+    Null,
+    Single(SqlParameter),
+    List(Vec<SqlParameter>),
+}
+
+#[derive(Debug, Clone)]
+struct Condition {
+    column: String,
+    operator: String,
+    value: ConditionValue,
+}
+
+/// Collects the SQL along with its parameters, in the placeholder and quoting style of the
+/// database.
+struct SqlBuilder {
+    sql: String,
+    parameters: Vec<SqlParameter>,
+    placeholder: Placeholder,
+    quote: char,
+}
+impl SqlBuilder {
+    fn new(db: &DatabaseType) -> Self {
+        Self {
+            sql: String::new(),
+            parameters: Vec::new(),
+            placeholder: db.placeholder(),
+            quote: match db {
+                DatabaseType::MySql(_) => '`',
+                _ => '"',
+            },
+        }
+    }
+
+    fn push(&mut self, sql: &str) {
+        self.sql.push_str(sql);
+    }
+
+    /// Quotes every part of a possibly qualified name, such as `schema.table`.
+    fn push_identifier(&mut self, name: &str) {
+        let quote = self.quote.to_string();
+        let quoted = name
+            .split('.')
+            .map(|part| {
+                if part == "*" {
+                    part.to_string()
+                } else {
+                    format!("{quote}{}{quote}", part.replace(&quote, &quote.repeat(2)))
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(".");
+        self.sql.push_str(&quoted);
+    }
+
+    fn push_identifiers(&mut self, names: &[String]) {
+        for (index, name) in names.iter().enumerate() {
+            if index > 0 {
+                self.push(", ");
+            }
+            self.push_identifier(name);
+        }
+    }
+
+    fn push_parameter(&mut self, parameter: SqlParameter) {
+        self.parameters.push(parameter);
+        match self.placeholder {
+            Placeholder::Numbered => self.sql.push_str(&format!("${}", self.parameters.len())),
+            Placeholder::Question => self.sql.push('?'),
+        }
+    }
+
+    fn push_parameters(&mut self, parameters: Vec<SqlParameter>) {
+        for (index, parameter) in parameters.into_iter().enumerate() {
+            if index > 0 {
+                self.push(", ");
+            }
+            self.push_parameter(parameter);
+        }
+    }
+}
+
+/// A query on a single table, built step by step from Lua. Every step returns a new builder,
+/// so a partial query can be reused.
+#[derive(Debug, Clone)]
+pub struct DatabaseTable {
+    pub db: DatabaseType,
+    pub table: String,
+    columns: Vec<String>,
+    conditions: Vec<Condition>,
+    order_by: Vec<(String, bool)>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+    returning: Vec<String>,
+}
+impl DatabaseTable {
+    pub fn new(db: DatabaseType, table: String) -> Self {
+        Self {
+            db,
+            table,
+            columns: Vec::new(),
+            conditions: Vec::new(),
+            order_by: Vec::new(),
+            limit: None,
+            offset: None,
+            returning: Vec::new(),
+        }
+    }
+
+    fn condition(
+        lua: &mlua::Lua,
+        column: String,
+        operator: &str,
+        value: mlua::Value,
+    ) -> mlua::Result<Condition> {
+        let operator = operator.trim().to_uppercase();
+        if !OPERATORS.contains(&operator.as_str()) {
+            return Err(mlua::Error::runtime(format!(
+                "Unsupported operator {operator}, expected one of {}",
+                OPERATORS.join(", ")
+            )));
+        }
+
+        let value = match value {
+            value
+                if (value.is_nil() || value.is_null())
+                    && matches!(operator.as_str(), "=" | "!=" | "<>") =>
+            {
+                ConditionValue::Null
+            }
+            value if value.is_nil() || value.is_null() => {
+                return Err(mlua::Error::runtime(format!(
+                    "The {operator} operator can not compare to NULL, use = or != instead"
+                )));
+            }
+            mlua::Value::Table(values) if operator.ends_with("IN") => ConditionValue::List(
+                values
+                    .sequence_values::<mlua::Value>()
+                    .map(|value| SqlParameter::from_lua(lua, value?))
+                    .collect::<mlua::Result<Vec<_>>>()?,
+            ),
+            _ if operator.ends_with("IN") => {
+                return Err(mlua::Error::runtime(format!(
+                    "The {operator} operator expects a list of values"
+                )));
+            }
+            value => ConditionValue::Single(SqlParameter::from_lua(lua, value)?),
+        };
+
+        Ok(Condition {
+            column,
+            operator,
+            value,
+        })
+    }
+
+    fn push_where(&self, builder: &mut SqlBuilder) {
+        for (index, condition) in self.conditions.iter().enumerate() {
+            builder.push(if index == 0 { " WHERE " } else { " AND " });
+
+            match &condition.value {
+                ConditionValue::Null => {
+                    builder.push_identifier(&condition.column);
+                    builder.push(match condition.operator.as_str() {
+                        "!=" | "<>" => " IS NOT NULL",
+                        _ => " IS NULL",
+                    });
+                }
+                // an empty list matches nothing, or everything when negated
+                ConditionValue::List(values) if values.is_empty() => {
+                    builder.push(if condition.operator == "IN" {
+                        "1 = 0"
+                    } else {
+                        "1 = 1"
+                    });
+                }
+                ConditionValue::List(values) => {
+                    builder.push_identifier(&condition.column);
+                    builder.push(&format!(" {} (", condition.operator));
+                    builder.push_parameters(values.clone());
+                    builder.push(")");
+                }
+                ConditionValue::Single(value) => {
+                    builder.push_identifier(&condition.column);
+                    builder.push(&format!(" {} ", condition.operator));
+                    builder.push_parameter(value.clone());
+                }
+            }
+        }
+    }
+
+    /// Refuses to change every row of the table unless it was asked for with `{ all = true }`,
+    /// as a missing `where` is more likely a mistake.
+    fn check_scope(&self, action: &str, options: Option<mlua::Table>) -> mlua::Result<()> {
+        let all = match options {
+            Some(options) => options.get::<Option<bool>>("all")?.unwrap_or_default(),
+            None => false,
+        };
+
+        if self.conditions.is_empty() && !all {
+            return Err(mlua::Error::runtime(format!(
+                "Refusing to {action} every row of {} without a where condition, pass {{ all = true }} to do so",
+                self.table
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn push_returning(&self, builder: &mut SqlBuilder) {
+        if !self.returning.is_empty() {
+            builder.push(" RETURNING ");
+            builder.push_identifiers(&self.returning);
+        }
+    }
+
+    /// The ordering and the limits are left out, as they do not change the count.
+    fn count_sql(&self) -> SqlBuilder {
+        let mut builder = SqlBuilder::new(&self.db);
+        builder.push("SELECT COUNT(*) AS count FROM ");
+        builder.push_identifier(&self.table);
+        self.push_where(&mut builder);
+
+        builder
+    }
+
+    fn select_sql(&self, limit: Option<i64>) -> SqlBuilder {
+        let mut builder = SqlBuilder::new(&self.db);
+
+        builder.push("SELECT ");
+        if self.columns.is_empty() {
+            builder.push("*");
+        } else {
+            builder.push_identifiers(&self.columns);
+        }
+        builder.push(" FROM ");
+        builder.push_identifier(&self.table);
+        self.push_where(&mut builder);
+
+        for (index, (column, descending)) in self.order_by.iter().enumerate() {
+            builder.push(if index == 0 { " ORDER BY " } else { ", " });
+            builder.push_identifier(column);
+            builder.push(if *descending { " DESC" } else { " ASC" });
+        }
+
+        match (limit.or(self.limit), self.offset) {
+            (Some(limit), offset) => {
+                builder.push(" LIMIT ");
+                builder.push_parameter(SqlParameter::Integer(limit));
+                if let Some(offset) = offset {
+                    builder.push(" OFFSET ");
+                    builder.push_parameter(SqlParameter::Integer(offset));
+                }
+            }
+            // SQLite and MySQL only accept an offset after a limit
+            (None, Some(offset)) => {
+                builder.push(match self.db {
+                    DatabaseType::Postgres(_) => " OFFSET ",
+                    DatabaseType::Sqlite(_) => " LIMIT -1 OFFSET ",
+                    DatabaseType::MySql(_) => " LIMIT 18446744073709551615 OFFSET ",
+                });
+                builder.push_parameter(SqlParameter::Integer(offset));
+            }
+            (None, None) => {}
+        }
+
+        builder
+    }
+
+    /// Accepts a single row or a list of rows. The columns are the union of the keys of
+    /// every row, and a row without one of them inserts the `DEFAULT` of the column there.
+    /// SQLite has no `DEFAULT` in `VALUES`, so each run of consecutive rows with the same keys
+    /// is inserted by a statement of its own instead.
+    fn insert_sql(&self, lua: &mlua::Lua, values: mlua::Table) -> mlua::Result<Vec<SqlBuilder>> {
+        let rows = if values.raw_len() > 0 {
+            values
+                .sequence_values::<mlua::Table>()
+                .collect::<mlua::Result<Vec<_>>>()?
+        } else {
+            vec![values]
+        };
+
+        let mut parsed_rows = Vec::new();
+        for row in rows {
+            let mut parsed = BTreeMap::new();
+            for pair in row.pairs::<String, mlua::Value>() {
+                let (column, value) = pair?;
+                parsed.insert(column, SqlParameter::from_lua(lua, value)?);
+            }
+            if parsed.is_empty() {
+                return Err(mlua::Error::runtime("A row to insert has no values"));
+            }
+            parsed_rows.push(parsed);
+        }
+
+        let groups = match self.db {
+            DatabaseType::Sqlite(_) => parsed_rows.chunk_by(|a, b| a.keys().eq(b.keys())).collect(),
+            _ => vec![parsed_rows.as_slice()],
+        };
+
+        let mut builders = Vec::new();
+        for group in groups {
+            let mut columns = group
+                .iter()
+                .flat_map(|row| row.keys().cloned())
+                .collect::<Vec<_>>();
+            columns.sort();
+            columns.dedup();
+
+            let mut builder = SqlBuilder::new(&self.db);
+            builder.push("INSERT INTO ");
+            builder.push_identifier(&self.table);
+            builder.push(" (");
+            builder.push_identifiers(&columns);
+            builder.push(") VALUES ");
+
+            for (index, row) in group.iter().enumerate() {
+                if index > 0 {
+                    builder.push(", ");
+                }
+                builder.push("(");
+                for (index, column) in columns.iter().enumerate() {
+                    if index > 0 {
+                        builder.push(", ");
+                    }
+                    match row.get(column) {
+                        Some(value) => builder.push_parameter(value.clone()),
+                        None => builder.push("DEFAULT"),
+                    }
+                }
+                builder.push(")");
+            }
+            self.push_returning(&mut builder);
+            builders.push(builder);
+        }
+
+        if builders.is_empty() {
+            return Err(mlua::Error::runtime("There are no values to insert"));
+        }
+
+        Ok(builders)
+    }
+
+    /// Runs the statements of an insert, in a transaction when there are several of them, and
+    /// merges their results.
+    async fn execute_inserts(
+        &self,
+        lua: &mlua::Lua,
+        mut builders: Vec<SqlBuilder>,
+    ) -> mlua::Result<mlua::Table> {
+        let pool = match &self.db {
+            DatabaseType::Sqlite(pool) if builders.len() > 1 => pool,
+            _ => {
+                let builder = builders.remove(0);
+                return self.db.execute(lua, &builder.sql, builder.parameters).await;
+            }
+        };
+
+        let mut transaction = pool
+            .begin()
+            .await
+            .map_err(|e| mlua::Error::runtime(format!("Error starting the transaction: {e:#?}")))?;
+
+        let mut rows_affected = 0;
+        let mut last_insert_id = None;
+        let rows = lua.create_table()?;
+        for builder in builders {
+            let result =
+                execute_sqlite(lua, &mut *transaction, &builder.sql, builder.parameters).await?;
+            rows_affected += result.get::<u64>("rows_affected")?;
+            last_insert_id = result
+                .get::<Option<i64>>("last_insert_id")?
+                .or(last_insert_id);
+            for row in result
+                .get::<mlua::Table>("rows")?
+                .sequence_values::<mlua::Value>()
+            {
+                rows.push(row?)?;
+            }
+        }
+
+        transaction.commit().await.map_err(|e| {
+            mlua::Error::runtime(format!("Error committing the transaction: {e:#?}"))
+        })?;
+
+        let result = lua.create_table()?;
+        result.set("rows_affected", rows_affected)?;
+        result.set("last_insert_id", last_insert_id)?;
+        result.set("rows", rows)?;
+
+        Ok(result)
+    }
+
+    fn update_sql(
+        &self,
+        lua: &mlua::Lua,
+        values: mlua::Table,
+        options: Option<mlua::Table>,
+    ) -> mlua::Result<SqlBuilder> {
+        self.check_scope("update", options)?;
+        let mut values = values
+            .pairs::<String, mlua::Value>()
+            .map(|pair| {
+                let (column, value) = pair?;
+                Ok((column, SqlParameter::from_lua(lua, value)?))
+            })
+            .collect::<mlua::Result<Vec<_>>>()?;
+        values.sort_by(|(a, _), (b, _)| a.cmp(b));
+        if values.is_empty() {
+            return Err(mlua::Error::runtime("There are no values to update"));
+        }
+
+        let mut builder = SqlBuilder::new(&self.db);
+        builder.push("UPDATE ");
+        builder.push_identifier(&self.table);
+        builder.push(" SET ");
+        for (index, (column, value)) in values.into_iter().enumerate() {
+            if index > 0 {
+                builder.push(", ");
+            }
+            builder.push_identifier(&column);
+            builder.push(" = ");
+            builder.push_parameter(value);
+        }
+        self.push_where(&mut builder);
+        self.push_returning(&mut builder);
+
+        Ok(builder)
+    }
+
+    fn delete_sql(&self, options: Option<mlua::Table>) -> mlua::Result<SqlBuilder> {
+        self.check_scope("delete", options)?;
+
+        let mut builder = SqlBuilder::new(&self.db);
+        builder.push("DELETE FROM ");
+        builder.push_identifier(&self.table);
+        self.push_where(&mut builder);
+        self.push_returning(&mut builder);
+
+        Ok(builder)
+    }
+}
+impl UserData for DatabaseTable {
+    fn add_methods<M: mlua::UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method_mut(
+            "where",
+            |lua, this, (column, operator, value): (mlua::Value, Option<String>, mlua::Value)| {
+                let mut table = this.clone();
+
+                match column {
+                    // `where{ column = value }`, where a list of values means any of them
+                    mlua::Value::Table(conditions) => {
+                        let mut conditions = conditions
+                            .pairs::<String, mlua::Value>()
+                            .map(|pair| {
+                                let (column, value) = pair?;
+                                let operator = match &value {
+                                    mlua::Value::Table(values) if values.raw_len() > 0 => "IN",
+                                    _ => "=",
+                                };
+                                Self::condition(lua, column, operator, value)
+                            })
+                            .collect::<mlua::Result<Vec<_>>>()?;
+                        conditions.sort_by(|a, b| a.column.cmp(&b.column));
+                        table.conditions.extend(conditions);
+                    }
+                    // `where(column, operator, value)`
+                    mlua::Value::String(column) => {
+                        let operator = operator.ok_or_else(|| {
+                            mlua::Error::runtime("The condition is missing its operator")
+                        })?;
+                        table.conditions.push(Self::condition(
+                            lua,
+                            column.to_str()?.to_string(),
+                            &operator,
+                            value,
+                        )?);
+                    }
+                    _ => {
+                        return Err(mlua::Error::runtime(
+                            "The condition is either a table of values or a column, an operator and a value",
+                        ));
+                    }
+                }
+
+                Ok(table)
+            },
+        );
+
+        methods.add_method_mut("select", |_, this, columns: Vec<String>| {
+            let mut table = this.clone();
+            table.columns = columns;
+
+            Ok(table)
+        });
+
+        methods.add_method_mut(
+            "order_by",
+            |_, this, (column, direction): (String, Option<String>)| {
+                let mut table = this.clone();
+                let descending = match direction.map(|direction| direction.to_lowercase()) {
+                    None => false,
+                    Some(direction) if direction == "asc" => false,
+                    Some(direction) if direction == "desc" => true,
+                    Some(direction) => {
+                        return Err(mlua::Error::runtime(format!(
+                            "Unsupported order {direction}, expected asc or desc"
+                        )));
+                    }
+                };
+                table.order_by.push((column, descending));
+
+                Ok(table)
+            },
+        );
+
+        methods.add_method_mut("limit", |_, this, limit: i64| {
+            let mut table = this.clone();
+            table.limit = Some(limit);
+
+            Ok(table)
+        });
+
+        methods.add_method_mut("offset", |_, this, offset: i64| {
+            let mut table = this.clone();
+            table.offset = Some(offset);
+
+            Ok(table)
+        });
+
+        methods.add_method_mut("returning", |_, this, columns: Vec<String>| {
+            let mut table = this.clone();
+            table.returning = columns;
+
+            Ok(table)
+        });
+
+        methods.add_async_method("all", |lua, this, ()| async move {
+            let builder = this.select_sql(None);
+            this.db
+                .query_all(&lua, &builder.sql, builder.parameters)
+                .await
+        });
+
+        methods.add_async_method("first", |lua, this, ()| async move {
+            let builder = this.select_sql(Some(1));
+            Ok(this
+                .db
+                .query_all(&lua, &builder.sql, builder.parameters)
+                .await?
+                .into_iter()
+                .next())
+        });
+
+        methods.add_async_method("count", |lua, this, ()| async move {
+            let builder = this.count_sql();
+            this.db
+                .query_one(&lua, &builder.sql, builder.parameters)
+                .await?
+                .get::<i64>("count")
+        });
+
+        methods.add_async_method("insert", |lua, this, values: mlua::Table| async move {
+            let builders = this.insert_sql(&lua, values)?;
+            this.execute_inserts(&lua, builders).await
+        });
+
+        methods.add_async_method(
+            "update",
+            |lua, this, (values, options): (mlua::Table, Option<mlua::Table>)| async move {
+                let builder = this.update_sql(&lua, values, options)?;
+                this.db
+                    .execute(&lua, &builder.sql, builder.parameters)
+                    .await
+            },
+        );
+
+        methods.add_async_method(
+            "delete",
+            |lua, this, options: Option<mlua::Table>| async move {
+                let builder = this.delete_sql(options)?;
+                this.db
+                    .execute(&lua, &builder.sql, builder.parameters)
+                    .await
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use mlua::IntoLua;
+    use sqlx::Pool;
+
+    // the pools connect lazily, so no database is needed to build the SQL
+    fn table(kind: &str) -> DatabaseTable {
+        let db = match kind {
+            "sqlite" => DatabaseType::Sqlite(Pool::connect_lazy("sqlite::memory:").unwrap()),
+            "postgres" => DatabaseType::Postgres(
+                Pool::connect_lazy("postgres://astra@localhost/astra").unwrap(),
+            ),
+            _ => DatabaseType::MySql(Pool::connect_lazy("mysql://astra@localhost/astra").unwrap()),
+        };
+        DatabaseTable::new(db, "users".to_string())
+    }
+
+    fn with_condition(
+        lua: &mlua::Lua,
+        table: &DatabaseTable,
+        column: &str,
+        operator: &str,
+        value: mlua::Value,
+    ) -> mlua::Result<DatabaseTable> {
+        let mut table = table.clone();
+        table.conditions.push(DatabaseTable::condition(
+            lua,
+            column.to_string(),
+            operator,
+            value,
+        )?);
+        Ok(table)
+    }
+
+    fn lua_table(lua: &mlua::Lua, source: &str) -> mlua::Table {
+        lua.load(source).eval::<mlua::Table>().unwrap()
+    }
+
+    #[tokio::test]
+    async fn builds_select_statements() {
+        let lua = mlua::Lua::new();
+        let mut users = with_condition(
+            &lua,
+            &table("postgres"),
+            "age",
+            ">=",
+            mlua::Value::Integer(18),
+        )
+        .unwrap();
+        users = with_condition(
+            &lua,
+            &users,
+            "id",
+            "in",
+            lua_table(&lua, "{ 1, 2 }").into_lua(&lua).unwrap(),
+        )
+        .unwrap();
+        users.columns = vec!["id".to_string(), "public.users.name".to_string()];
+        users.order_by = vec![("age".to_string(), true)];
+        users.limit = Some(10);
+        users.offset = Some(20);
+
+        let builder = users.select_sql(None);
+        assert_eq!(
+            builder.sql,
+            "SELECT \"id\", \"public\".\"users\".\"name\" FROM \"users\" WHERE \"age\" >= $1 AND \"id\" IN ($2, $3) ORDER BY \"age\" DESC LIMIT $4 OFFSET $5"
+        );
+        assert_eq!(builder.parameters.len(), 5);
+
+        assert_eq!(
+            users.count_sql().sql,
+            "SELECT COUNT(*) AS count FROM \"users\" WHERE \"age\" >= $1 AND \"id\" IN ($2, $3)"
+        );
+    }
+
+    #[tokio::test]
+    async fn quotes_and_places_parameters_for_each_database() {
+        let lua = mlua::Lua::new();
+        let value = || mlua::Value::Integer(1);
+
+        let mut mysql = with_condition(&lua, &table("mysql"), "we`ird", "=", value()).unwrap();
+        mysql.offset = Some(5);
+        assert_eq!(
+            mysql.select_sql(None).sql,
+            "SELECT * FROM `users` WHERE `we``ird` = ? LIMIT 18446744073709551615 OFFSET ?"
+        );
+
+        let mut sqlite = with_condition(&lua, &table("sqlite"), "we\"ird", "=", value()).unwrap();
+        sqlite.offset = Some(5);
+        assert_eq!(
+            sqlite.select_sql(Some(1)).sql,
+            "SELECT * FROM \"users\" WHERE \"we\"\"ird\" = $1 LIMIT $2 OFFSET $3"
+        );
+    }
+
+    #[tokio::test]
+    async fn builds_insert_statements() {
+        let lua = mlua::Lua::new();
+        let mut users = table("postgres");
+        users.returning = vec!["id".to_string()];
+
+        let builders = users
+            .insert_sql(
+                &lua,
+                lua_table(
+                    &lua,
+                    "{ { name = 'a', age = 1 }, { name = 'b', email = 'b@b' } }",
+                ),
+            )
+            .unwrap();
+        assert_eq!(builders.len(), 1);
+        assert_eq!(
+            builders[0].sql,
+            "INSERT INTO \"users\" (\"age\", \"email\", \"name\") VALUES ($1, DEFAULT, $2), (DEFAULT, $3, $4) RETURNING \"id\""
+        );
+        assert!(matches!(
+            builders[0].parameters.as_slice(),
+            [
+                SqlParameter::Integer(1),
+                SqlParameter::Text(_),
+                SqlParameter::Text(_),
+                SqlParameter::Text(_),
+            ]
+        ));
+
+        assert!(users.insert_sql(&lua, lua.create_table().unwrap()).is_err());
+        assert!(
+            users
+                .insert_sql(&lua, lua_table(&lua, "{ { name = 'a' }, {} }"))
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn splits_sqlite_inserts_by_keys() {
+        let lua = mlua::Lua::new();
+        let users = table("sqlite");
+
+        let builders = users
+            .insert_sql(
+                &lua,
+                lua_table(
+                    &lua,
+                    "{ { name = 'a' }, { name = 'b' }, { name = 'c', age = 3 }, { name = 'd' } }",
+                ),
+            )
+            .unwrap();
+        let sql = builders
+            .iter()
+            .map(|builder| builder.sql.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sql,
+            [
+                "INSERT INTO \"users\" (\"name\") VALUES ($1), ($2)",
+                "INSERT INTO \"users\" (\"age\", \"name\") VALUES ($1, $2)",
+                "INSERT INTO \"users\" (\"name\") VALUES ($1)",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn keeps_the_defaults_of_missing_cells() {
+        let lua = mlua::Lua::new();
+        // a single connection, as every connection has its own database in memory
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::query(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, role TEXT DEFAULT 'guest')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let mut users = DatabaseTable::new(DatabaseType::Sqlite(pool.clone()), "users".to_string());
+        users.returning = vec!["name".to_string()];
+
+        let builders = users
+            .insert_sql(
+                &lua,
+                lua_table(
+                    &lua,
+                    "{ { name = 'a' }, { name = 'b', role = 'admin' }, { name = 'c' } }",
+                ),
+            )
+            .unwrap();
+        let result = users.execute_inserts(&lua, builders).await.unwrap();
+        assert_eq!(result.get::<u64>("rows_affected").unwrap(), 3);
+        assert_eq!(result.get::<i64>("last_insert_id").unwrap(), 3);
+        assert_eq!(result.get::<mlua::Table>("rows").unwrap().raw_len(), 3);
+
+        let roles = sqlx::query_scalar::<_, String>("SELECT role FROM users ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(roles, ["guest", "admin", "guest"]);
+    }
+
+    #[tokio::test]
+    async fn checks_null_with_equality_only() {
+        let lua = mlua::Lua::new();
+        let users = table("sqlite");
+
+        for (operator, sql) in [
+            ("=", " IS NULL"),
+            ("!=", " IS NOT NULL"),
+            ("<>", " IS NOT NULL"),
+        ] {
+            for value in [mlua::Value::Nil, mlua::Value::NULL] {
+                let users = with_condition(&lua, &users, "email", operator, value).unwrap();
+                assert_eq!(
+                    users.count_sql().sql,
+                    format!("SELECT COUNT(*) AS count FROM \"users\" WHERE \"email\"{sql}")
+                );
+            }
+        }
+
+        for operator in ["<", ">=", "like", "not like", "in", "not in"] {
+            for value in [mlua::Value::Nil, mlua::Value::NULL] {
+                assert!(with_condition(&lua, &users, "email", operator, value).is_err());
+            }
+        }
+        assert!(with_condition(&lua, &users, "email", "between", mlua::Value::Integer(1)).is_err());
+        assert!(with_condition(&lua, &users, "id", "in", mlua::Value::Integer(1)).is_err());
+    }
+
+    #[tokio::test]
+    async fn matches_nothing_with_an_empty_list() {
+        let lua = mlua::Lua::new();
+        let users = table("sqlite");
+
+        let empty = with_condition(
+            &lua,
+            &users,
+            "id",
+            "in",
+            lua_table(&lua, "{}").into_lua(&lua).unwrap(),
+        )
+        .unwrap();
+        assert!(empty.count_sql().sql.ends_with(" WHERE 1 = 0"));
+
+        let empty = with_condition(
+            &lua,
+            &users,
+            "id",
+            "not in",
+            lua_table(&lua, "{}").into_lua(&lua).unwrap(),
+        )
+        .unwrap();
+        assert!(empty.count_sql().sql.ends_with(" WHERE 1 = 1"));
+    }
+
+    #[tokio::test]
+    async fn requires_a_condition_to_update_or_delete() {
+        let lua = mlua::Lua::new();
+        let users = table("postgres");
+        let values = || lua_table(&lua, "{ name = 'a', age = 2 }");
+        let all = || Some(lua_table(&lua, "{ all = true }"));
+
+        let error = users.update_sql(&lua, values(), None).err().unwrap();
+        assert!(error.to_string().contains("without a where condition"));
+        assert!(users.delete_sql(None).is_err());
+        assert!(
+            users
+                .delete_sql(Some(lua_table(&lua, "{ all = false }")))
+                .is_err()
+        );
+
+        assert_eq!(
+            users.update_sql(&lua, values(), all()).unwrap().sql,
+            "UPDATE \"users\" SET \"age\" = $1, \"name\" = $2"
+        );
+        assert_eq!(
+            users.delete_sql(all()).unwrap().sql,
+            "DELETE FROM \"users\""
+        );
+
+        let mut one = with_condition(&lua, &users, "id", "=", mlua::Value::Integer(7)).unwrap();
+        one.returning = vec!["id".to_string()];
+        assert_eq!(
+            one.update_sql(&lua, values(), None).unwrap().sql,
+            "UPDATE \"users\" SET \"age\" = $1, \"name\" = $2 WHERE \"id\" = $3 RETURNING \"id\""
+        );
+        assert_eq!(
+            one.delete_sql(None).unwrap().sql,
+            "DELETE FROM \"users\" WHERE \"id\" = $1 RETURNING \"id\""
+        );
+        assert!(
+            users
+                .update_sql(&lua, lua.create_table().unwrap(), all())
+                .is_err()
+        );
+    }
+}
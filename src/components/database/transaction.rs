@@ -0,0 +1,191 @@
+use super::{
+    DatabaseType, bind_mysql, bind_postgres, bind_sqlite, execute_mysql, execute_postgres,
+    execute_sqlite, parse_sql_to_lua_mysql, parse_sql_to_lua_postgres, parse_sql_to_lua_sqlite,
+    statement::{Placeholder, parse_statement},
+};
+use mlua::UserData;
+use sqlx::{MySql, Postgres, Sqlite, Transaction};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+pub enum TransactionType {
+    Sqlite(Transaction<'static, Sqlite>),
+    Postgres(Transaction<'static, Postgres>),
+    MySql(Transaction<'static, MySql>),
+}
+impl TransactionType {
+    fn placeholder(&self) -> Placeholder {
+        match self {
+            TransactionType::Sqlite(_) | TransactionType::Postgres(_) => Placeholder::Numbered,
+            TransactionType::MySql(_) => Placeholder::Question,
+        }
+    }
+}
+
+fn finished() -> mlua::Error {
+    mlua::Error::runtime("The transaction is already finished")
+}
+
+/// A transaction on a single connection of the pool. It is shared between the clones
+/// so it can be finished from Lua or from the `Database:transaction` wrapper.
+#[derive(Clone)]
+pub struct DatabaseTransaction {
+    pub transaction: Arc<Mutex<Option<TransactionType>>>,
+}
+impl DatabaseTransaction {
+    pub async fn begin(db: &DatabaseType) -> mlua::Result<Self> {
+        let transaction = match db {
+            DatabaseType::Sqlite(pool) => pool.begin().await.map(TransactionType::Sqlite),
+            DatabaseType::Postgres(pool) => pool.begin().await.map(TransactionType::Postgres),
+            DatabaseType::MySql(pool) => pool.begin().await.map(TransactionType::MySql),
+        };
+
+        match transaction {
+            Ok(transaction) => Ok(Self {
+                transaction: Arc::new(Mutex::new(Some(transaction))),
+            }),
+            Err(e) => Err(mlua::Error::runtime(format!(
+                "Error starting the transaction: {e:#?}"
+            ))),
+        }
+    }
+
+    pub async fn is_open(&self) -> bool {
+        self.transaction.lock().await.is_some()
+    }
+
+    pub async fn commit(&self) -> mlua::Result<()> {
+        let result = match self.transaction.lock().await.take() {
+            Some(TransactionType::Sqlite(transaction)) => transaction.commit().await,
+            Some(TransactionType::Postgres(transaction)) => transaction.commit().await,
+            Some(TransactionType::MySql(transaction)) => transaction.commit().await,
+            None => return Err(finished()),
+        };
+
+        result
+            .map_err(|e| mlua::Error::runtime(format!("Error committing the transaction: {e:#?}")))
+    }
+
+    pub async fn rollback(&self) -> mlua::Result<()> {
+        let result = match self.transaction.lock().await.take() {
+            Some(TransactionType::Sqlite(transaction)) => transaction.rollback().await,
+            Some(TransactionType::Postgres(transaction)) => transaction.rollback().await,
+            Some(TransactionType::MySql(transaction)) => transaction.rollback().await,
+            None => return Err(finished()),
+        };
+
+        result.map_err(|e| {
+            mlua::Error::runtime(format!("Error rolling back the transaction: {e:#?}"))
+        })
+    }
+}
+impl UserData for DatabaseTransaction {
+    fn add_methods<M: mlua::UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_async_method(
+            "execute",
+            |lua, this, (sql, parameters): (String, Option<mlua::Table>)| async move {
+                let mut transaction = this.transaction.lock().await;
+                let transaction = transaction.as_mut().ok_or_else(finished)?;
+                let (sql, parameters) =
+                    parse_statement(&lua, &sql, parameters, transaction.placeholder())?;
+
+                match transaction {
+                    TransactionType::Sqlite(transaction) => {
+                        execute_sqlite(&lua, &mut **transaction, &sql, parameters).await
+                    }
+                    TransactionType::Postgres(transaction) => {
+                        execute_postgres(&lua, &mut **transaction, &sql, parameters).await
+                    }
+                    TransactionType::MySql(transaction) => {
+                        execute_mysql(&lua, &mut **transaction, &sql, parameters).await
+                    }
+                }
+            },
+        );
+
+        methods.add_async_method(
+            "query_one",
+            |lua, this, (sql, parameters): (String, Option<mlua::Table>)| async move {
+                let mut transaction = this.transaction.lock().await;
+                let transaction = transaction.as_mut().ok_or_else(finished)?;
+                let (sql, parameters) =
+                    parse_statement(&lua, &sql, parameters, transaction.placeholder())?;
+
+                let result = match transaction {
+                    TransactionType::Sqlite(transaction) => {
+                        match bind_sqlite(&sql, parameters)
+                            .fetch_one(&mut **transaction)
+                            .await
+                        {
+                            Ok(row) => Ok(parse_sql_to_lua_sqlite(&lua, &row)?),
+                            Err(e) => Err(e),
+                        }
+                    }
+                    TransactionType::Postgres(transaction) => {
+                        match bind_postgres(&sql, parameters)
+                            .fetch_one(&mut **transaction)
+                            .await
+                        {
+                            Ok(row) => Ok(parse_sql_to_lua_postgres(&lua, &row)?),
+                            Err(e) => Err(e),
+                        }
+                    }
+                    TransactionType::MySql(transaction) => {
+                        match bind_mysql(&sql, parameters)
+                            .fetch_one(&mut **transaction)
+                            .await
+                        {
+                            Ok(row) => Ok(parse_sql_to_lua_mysql(&lua, &row)?),
+                            Err(e) => Err(e),
+                        }
+                    }
+                };
+
+                result
+                    .map_err(|e| mlua::Error::runtime(format!("Error executing the query: {e:#?}")))
+            },
+        );
+
+        methods.add_async_method(
+            "query_all",
+            |lua, this, (sql, parameters): (String, Option<mlua::Table>)| async move {
+                let mut transaction = this.transaction.lock().await;
+                let transaction = transaction.as_mut().ok_or_else(finished)?;
+                let (sql, parameters) =
+                    parse_statement(&lua, &sql, parameters, transaction.placeholder())?;
+                let error = |e| mlua::Error::runtime(format!("Error executing the query: {e:#?}"));
+
+                match transaction {
+                    TransactionType::Sqlite(transaction) => bind_sqlite(&sql, parameters)
+                        .fetch_all(&mut **transaction)
+                        .await
+                        .map_err(error)?
+                        .iter()
+                        .map(|row| parse_sql_to_lua_sqlite(&lua, row))
+                        .collect::<mlua::Result<Vec<_>>>(),
+                    TransactionType::Postgres(transaction) => bind_postgres(&sql, parameters)
+                        .fetch_all(&mut **transaction)
+                        .await
+                        .map_err(error)?
+                        .iter()
+                        .map(|row| parse_sql_to_lua_postgres(&lua, row))
+                        .collect::<mlua::Result<Vec<_>>>(),
+                    TransactionType::MySql(transaction) => bind_mysql(&sql, parameters)
+                        .fetch_all(&mut **transaction)
+                        .await
+                        .map_err(error)?
+                        .iter()
+                        .map(|row| parse_sql_to_lua_mysql(&lua, row))
+                        .collect::<mlua::Result<Vec<_>>>(),
+                }
+            },
+        );
+
+        methods.add_async_method("commit", |_, this, ()| async move { this.commit().await });
+
+        methods.add_async_method(
+            "rollback",
+            |_, this, ()| async move { this.rollback().await },
+        );
+    }
+}
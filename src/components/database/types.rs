@@ -0,0 +1,537 @@
+use crate::components::datetime::LuaDateTime;
+use mlua::{IntoLua, LuaSerdeExt};
+use sqlx::{
+    Column, MySql, Postgres, Row, Sqlite, TypeInfo, ValueRef,
+    mysql::{MySqlArguments, MySqlRow},
+    postgres::{PgArguments, PgRow, PgTypeKind},
+    query::Query,
+    sqlite::{SqliteArguments, SqliteRow},
+    types::{BigDecimal, ipnetwork::IpNetwork},
+};
+
+/// Binary data wrapped by `Astra.database_bytes`, bound as `BLOB` or `BYTEA` even when the bytes
+/// happen to be valid UTF-8.
+#[derive(Debug, Clone)]
+pub struct DatabaseBytes(pub Vec<u8>);
+impl mlua::UserData for DatabaseBytes {}
+
+/// A Lua value converted ahead of binding, so that every database binds the same set of types.
+#[derive(Debug, Clone)]
+pub enum SqlParameter {
+    Null,
+    Integer(i64),
+    Number(f64),
+    Boolean(bool),
+    Text(String),
+    Bytes(Vec<u8>),
+    DateTime(chrono::DateTime<chrono::FixedOffset>),
+    Json(serde_json::Value),
+    IntegerArray(Vec<i64>),
+    NumberArray(Vec<f64>),
+    BooleanArray(Vec<bool>),
+    TextArray(Vec<String>),
+}
+impl SqlParameter {
+    pub fn from_lua(lua: &mlua::Lua, value: mlua::Value) -> mlua::Result<Self> {
+        match value {
+            value if value.is_null() => Ok(Self::Null),
+            mlua::Value::Integer(_) => Ok(Self::Integer(value.as_i64().unwrap_or_default())),
+            mlua::Value::Number(value) => Ok(Self::Number(value)),
+            mlua::Value::Boolean(value) => Ok(Self::Boolean(value)),
+            // strings that are not valid UTF-8 can only be binary data
+            mlua::Value::String(value) => match value.to_str() {
+                Ok(text) => Ok(Self::Text(text.to_string())),
+                Err(_) => Ok(Self::Bytes(value.as_bytes().to_vec())),
+            },
+            mlua::Value::UserData(value) if value.is::<LuaDateTime>() => {
+                Ok(Self::DateTime(value.borrow::<LuaDateTime>()?.dt))
+            }
+            mlua::Value::UserData(value) if value.is::<DatabaseBytes>() => {
+                Ok(Self::Bytes(value.borrow::<DatabaseBytes>()?.0.clone()))
+            }
+            mlua::Value::Table(table) => Self::from_lua_table(lua, table),
+            value => Err(mlua::Error::runtime(format!(
+                "Unsupported SQL parameter of type {}",
+                value.type_name()
+            ))),
+        }
+    }
+
+    /// Sequences of a single scalar type become arrays, everything else becomes JSON.
+    fn from_lua_table(lua: &mlua::Lua, table: mlua::Table) -> mlua::Result<Self> {
+        // `Astra.datetime.new` wraps the userdata in a proxy table
+        if let Ok(mlua::Value::UserData(datetime)) = table.raw_get::<mlua::Value>("_obj")
+            && datetime.is::<LuaDateTime>()
+        {
+            return Ok(Self::DateTime(datetime.borrow::<LuaDateTime>()?.dt));
+        }
+
+        let values = table
+            .sequence_values::<mlua::Value>()
+            .collect::<mlua::Result<Vec<_>>>()?;
+        let is_sequence =
+            !values.is_empty() && table.pairs::<mlua::Value, mlua::Value>().count() == values.len();
+
+        if is_sequence {
+            if values.iter().all(|value| value.is_integer()) {
+                return Ok(Self::IntegerArray(
+                    values.iter().filter_map(|value| value.as_i64()).collect(),
+                ));
+            } else if values
+                .iter()
+                .all(|value| value.is_number() || value.is_integer())
+            {
+                return Ok(Self::NumberArray(
+                    values.iter().filter_map(|value| value.as_f64()).collect(),
+                ));
+            } else if values.iter().all(|value| value.is_boolean()) {
+                return Ok(Self::BooleanArray(
+                    values
+                        .iter()
+                        .filter_map(|value| value.as_boolean())
+                        .collect(),
+                ));
+            } else if values.iter().all(|value| value.is_string()) {
+                return Ok(Self::TextArray(
+                    values
+                        .iter()
+                        .filter_map(|value| value.as_string())
+                        .map(|value| value.to_string_lossy())
+                        .collect(),
+                ));
+            }
+        }
+
+        Ok(Self::Json(lua.from_value::<serde_json::Value>(
+            mlua::Value::Table(table),
+        )?))
+    }
+
+    /// Arrays are only native to Postgres, the rest of the databases store them as JSON.
+    fn array_as_json(&self) -> Option<serde_json::Value> {
+        match self {
+            Self::IntegerArray(values) => Some(serde_json::json!(values)),
+            Self::NumberArray(values) => Some(serde_json::json!(values)),
+            Self::BooleanArray(values) => Some(serde_json::json!(values)),
+            Self::TextArray(values) => Some(serde_json::json!(values)),
+            _ => None,
+        }
+    }
+}
+
+/// Converts the positional parameters. As `nil` leaves a hole in the table, the number of
+/// parameters is taken from the highest index and the holes are bound as SQL `NULL`. An index
+/// beyond the placeholders of the statement is refused, rather than filling the holes up to it.
+pub fn parse_parameters(
+    lua: &mlua::Lua,
+    parameters: Option<mlua::Table>,
+    placeholders: usize,
+) -> mlua::Result<Vec<SqlParameter>> {
+    let Some(parameters) = parameters else {
+        return Ok(Vec::new());
+    };
+
+    let mut values = std::collections::BTreeMap::new();
+    for pair in parameters.pairs::<mlua::Value, mlua::Value>() {
+        let (key, value) = pair?;
+        if let Some(index) = key.as_i64()
+            && index >= 1
+        {
+            if index as u64 > placeholders as u64 {
+                return Err(mlua::Error::runtime(format!(
+                    "The parameter {index} is beyond the {placeholders} placeholders of the statement"
+                )));
+            }
+            values.insert(index as usize, SqlParameter::from_lua(lua, value)?);
+        }
+    }
+
+    let length = values.keys().next_back().copied().unwrap_or_default();
+    Ok((1..=length)
+        .map(|index| values.remove(&index).unwrap_or(SqlParameter::Null))
+        .collect())
+}
+
+/// An untyped `NULL` for Postgres, so the server infers the type from the statement
+/// instead of rejecting a mismatched type.
+struct PgNull;
+impl sqlx::Type<Postgres> for PgNull {
+    fn type_info() -> sqlx::postgres::PgTypeInfo {
+        sqlx::postgres::PgTypeInfo::with_oid(sqlx::postgres::types::Oid(0))
+    }
+}
+impl sqlx::Encode<'_, Postgres> for PgNull {
+    fn encode_by_ref(
+        &self,
+        _: &mut sqlx::postgres::PgArgumentBuffer,
+    ) -> Result<sqlx::encode::IsNull, sqlx::error::BoxDynError> {
+        Ok(sqlx::encode::IsNull::Yes)
+    }
+}
+
+pub fn bind_postgres(sql: &str, parameters: Vec<SqlParameter>) -> Query<'_, Postgres, PgArguments> {
+    let mut query = sqlx::query(sql);
+
+    for parameter in parameters {
+        query = match parameter {
+            SqlParameter::Null => query.bind(PgNull),
+            SqlParameter::Integer(value) => query.bind(value),
+            SqlParameter::Number(value) => query.bind(value),
+            SqlParameter::Boolean(value) => query.bind(value),
+            SqlParameter::Text(value) => query.bind(value),
+            SqlParameter::Bytes(value) => query.bind(value),
+            SqlParameter::DateTime(value) => query.bind(value),
+            SqlParameter::Json(value) => query.bind(value),
+            SqlParameter::IntegerArray(value) => query.bind(value),
+            SqlParameter::NumberArray(value) => query.bind(value),
+            SqlParameter::BooleanArray(value) => query.bind(value),
+            SqlParameter::TextArray(value) => query.bind(value),
+        };
+    }
+
+    query
+}
+
+pub fn bind_sqlite(
+    sql: &str,
+    parameters: Vec<SqlParameter>,
+) -> Query<'_, Sqlite, SqliteArguments<'_>> {
+    let mut query = sqlx::query(sql);
+
+    for parameter in parameters {
+        query = match parameter {
+            SqlParameter::Null => query.bind(None::<String>),
+            SqlParameter::Integer(value) => query.bind(value),
+            SqlParameter::Number(value) => query.bind(value),
+            SqlParameter::Boolean(value) => query.bind(value),
+            SqlParameter::Text(value) => query.bind(value),
+            SqlParameter::Bytes(value) => query.bind(value),
+            SqlParameter::DateTime(value) => query.bind(value),
+            SqlParameter::Json(value) => query.bind(value),
+            array => query.bind(array.array_as_json()),
+        };
+    }
+
+    query
+}
+
+pub fn bind_mysql(sql: &str, parameters: Vec<SqlParameter>) -> Query<'_, MySql, MySqlArguments> {
+    let mut query = sqlx::query(sql);
+
+    for parameter in parameters {
+        query = match parameter {
+            SqlParameter::Null => query.bind(None::<String>),
+            SqlParameter::Integer(value) => query.bind(value),
+            SqlParameter::Number(value) => query.bind(value),
+            SqlParameter::Boolean(value) => query.bind(value),
+            SqlParameter::Text(value) => query.bind(value),
+            SqlParameter::Bytes(value) => query.bind(value),
+            // MySQL has no timezone aware type, the `TIMESTAMP` columns are stored in UTC
+            SqlParameter::DateTime(value) => query.bind(value.with_timezone(&chrono::Utc)),
+            SqlParameter::Json(value) => query.bind(value),
+            array => query.bind(array.array_as_json()),
+        };
+    }
+
+    query
+}
+
+/// Conversion of a decoded column value into its Lua representation.
+trait IntoLuaSql {
+    fn into_lua_sql(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value>;
+}
+macro_rules! impl_into_lua_sql {
+    ($($ty:ty),* => into_lua) => {
+        $(impl IntoLuaSql for $ty {
+            fn into_lua_sql(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+                self.into_lua(lua)
+            }
+        })*
+    };
+    ($($ty:ty),* => to_value) => {
+        $(impl IntoLuaSql for $ty {
+            fn into_lua_sql(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+                lua.to_value(&self)
+            }
+        })*
+    };
+    ($($ty:ty),* => to_string) => {
+        $(impl IntoLuaSql for $ty {
+            fn into_lua_sql(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+                self.to_string().into_lua(lua)
+            }
+        })*
+    };
+}
+impl_into_lua_sql!(bool, i8, i16, i32, i64, u8, u16, u32, u64, f32, f64, String => into_lua);
+impl_into_lua_sql!(
+    serde_json::Value,
+    chrono::DateTime<chrono::Utc>,
+    chrono::NaiveDateTime,
+    chrono::NaiveDate,
+    chrono::NaiveTime => to_value
+);
+// decimals are kept as strings so that no precision is lost
+impl_into_lua_sql!(BigDecimal, IpNetwork, uuid::Uuid => to_string);
+impl IntoLuaSql for Vec<u8> {
+    fn into_lua_sql(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        Ok(mlua::Value::String(lua.create_string(&self)?))
+    }
+}
+
+fn into_lua_array<T: IntoLuaSql>(
+    lua: &mlua::Lua,
+    values: Vec<Option<T>>,
+) -> mlua::Result<mlua::Value> {
+    let table = lua.create_table()?;
+    for value in values {
+        match value {
+            Some(value) => table.push(value.into_lua_sql(lua)?)?,
+            None => table.push(mlua::Value::NULL)?,
+        }
+    }
+
+    Ok(mlua::Value::Table(table))
+}
+
+fn decode_error(column: &str, e: sqlx::Error) -> mlua::Error {
+    mlua::Error::runtime(format!("Could not read the column {column}: {e}"))
+}
+
+fn postgres_column_to_lua(lua: &mlua::Lua, row: &PgRow, index: usize) -> mlua::Result<mlua::Value> {
+    let column = row.column(index);
+    let raw = row
+        .try_get_raw(index)
+        .map_err(|e| decode_error(column.name(), e))?;
+    if raw.is_null() {
+        return Ok(mlua::Value::NULL);
+    }
+
+    macro_rules! get {
+        ($ty:ty) => {
+            row.try_get::<$ty, _>(index)
+                .map_err(|e| decode_error(column.name(), e))?
+                .into_lua_sql(lua)
+        };
+    }
+    macro_rules! get_array {
+        ($ty:ty) => {
+            into_lua_array(
+                lua,
+                row.try_get::<Vec<Option<$ty>>, _>(index)
+                    .map_err(|e| decode_error(column.name(), e))?,
+            )
+        };
+    }
+
+    match column.type_info().name() {
+        "BOOL" => get!(bool),
+        "INT2" => get!(i16),
+        "INT4" => get!(i32),
+        "INT8" => get!(i64),
+        "FLOAT4" => get!(f32),
+        "FLOAT8" => get!(f64),
+        "NUMERIC" => get!(BigDecimal),
+        "TEXT" | "VARCHAR" | "BPCHAR" | "NAME" | "CHAR" => get!(String),
+        "BYTEA" => get!(Vec<u8>),
+        "JSON" | "JSONB" => get!(serde_json::Value),
+        "UUID" => get!(uuid::Uuid),
+        "TIMESTAMPTZ" => get!(chrono::DateTime<chrono::Utc>),
+        "TIMESTAMP" => get!(chrono::NaiveDateTime),
+        "DATE" => get!(chrono::NaiveDate),
+        "TIME" => get!(chrono::NaiveTime),
+        "INET" | "CIDR" => get!(IpNetwork),
+        "BOOL[]" => get_array!(bool),
+        "INT2[]" => get_array!(i16),
+        "INT4[]" => get_array!(i32),
+        "INT8[]" => get_array!(i64),
+        "FLOAT4[]" => get_array!(f32),
+        "FLOAT8[]" => get_array!(f64),
+        "NUMERIC[]" => get_array!(BigDecimal),
+        "TEXT[]" | "VARCHAR[]" | "BPCHAR[]" | "NAME[]" | "CHAR[]" => get_array!(String),
+        "BYTEA[]" => get_array!(Vec<u8>),
+        "JSON[]" | "JSONB[]" => get_array!(serde_json::Value),
+        "UUID[]" => get_array!(uuid::Uuid),
+        "TIMESTAMPTZ[]" => get_array!(chrono::DateTime<chrono::Utc>),
+        "TIMESTAMP[]" => get_array!(chrono::NaiveDateTime),
+        "DATE[]" => get_array!(chrono::NaiveDate),
+        "TIME[]" => get_array!(chrono::NaiveTime),
+        "INET[]" | "CIDR[]" => get_array!(IpNetwork),
+        // the binary value of the enums is their label, the other types need their own decoding
+        _ if is_text_like(column.type_info()) => {
+            match <String as sqlx::Decode<Postgres>>::decode(raw) {
+                Ok(value) => value.into_lua(lua),
+                Err(e) => Err(decode_error(column.name(), sqlx::Error::Decode(e))),
+            }
+        }
+        type_name => Err(unsupported_type(type_name, column.name())),
+    }
+}
+
+/// Whether the binary value of the type is its text, as for the enums, the text types and the
+/// domains over them.
+fn is_text_like(type_info: &sqlx::postgres::PgTypeInfo) -> bool {
+    match type_info.kind() {
+        PgTypeKind::Enum(_) => true,
+        PgTypeKind::Domain(base) => is_text_like(base),
+        PgTypeKind::Simple => matches!(
+            type_info.name(),
+            "TEXT" | "VARCHAR" | "BPCHAR" | "NAME" | "CHAR" | "UNKNOWN" | "citext"
+        ),
+        _ => false,
+    }
+}
+
+fn unsupported_type(type_name: &str, column: &str) -> mlua::Error {
+    mlua::Error::runtime(format!(
+        "Unsupported column type {type_name} of the column {column}, consider casting it in the query"
+    ))
+}
+
+fn sqlite_column_to_lua(
+    lua: &mlua::Lua,
+    row: &SqliteRow,
+    index: usize,
+) -> mlua::Result<mlua::Value> {
+    let column = row.column(index);
+    let raw = row
+        .try_get_raw(index)
+        .map_err(|e| decode_error(column.name(), e))?;
+
+    macro_rules! get {
+        ($ty:ty) => {
+            row.try_get_unchecked::<$ty, _>(index)
+                .map_err(|e| decode_error(column.name(), e))?
+                .into_lua_sql(lua)
+        };
+    }
+
+    // SQLite is dynamically typed, so the storage class of the value decides the type,
+    // with the exception of the booleans which are stored as integers
+    match (column.type_info().name(), raw.type_info().name()) {
+        (_, "NULL") => Ok(mlua::Value::NULL),
+        ("BOOLEAN", "INTEGER") => get!(bool),
+        (_, "INTEGER") => get!(i64),
+        (_, "REAL") => get!(f64),
+        (_, "BLOB") => get!(Vec<u8>),
+        _ => get!(String),
+    }
+}
+
+fn mysql_column_to_lua(lua: &mlua::Lua, row: &MySqlRow, index: usize) -> mlua::Result<mlua::Value> {
+    let column = row.column(index);
+    let raw = row
+        .try_get_raw(index)
+        .map_err(|e| decode_error(column.name(), e))?;
+    if raw.is_null() {
+        return Ok(mlua::Value::NULL);
+    }
+
+    // the type is already matched by its name, so the compatibility checks of sqlx are
+    // skipped, as they also depend on flags such as the unsigned one of `YEAR`
+    macro_rules! get {
+        ($ty:ty) => {
+            row.try_get_unchecked::<$ty, _>(index)
+                .map_err(|e| decode_error(column.name(), e))?
+                .into_lua_sql(lua)
+        };
+    }
+
+    match column.type_info().name() {
+        "BOOLEAN" => get!(bool),
+        "TINYINT" => get!(i8),
+        "SMALLINT" => get!(i16),
+        "MEDIUMINT" | "INT" => get!(i32),
+        "BIGINT" => get!(i64),
+        "TINYINT UNSIGNED" => get!(u8),
+        "SMALLINT UNSIGNED" | "YEAR" => get!(u16),
+        "MEDIUMINT UNSIGNED" | "INT UNSIGNED" => get!(u32),
+        "BIGINT UNSIGNED" | "BIT" => get!(u64),
+        "FLOAT" => get!(f32),
+        "DOUBLE" => get!(f64),
+        "DECIMAL" => get!(BigDecimal),
+        "CHAR" | "VARCHAR" | "TINYTEXT" | "TEXT" | "MEDIUMTEXT" | "LONGTEXT" | "ENUM" | "SET" => {
+            get!(String)
+        }
+        "BINARY" | "VARBINARY" | "TINYBLOB" | "BLOB" | "MEDIUMBLOB" | "LONGBLOB" => {
+            get!(Vec<u8>)
+        }
+        "JSON" => get!(serde_json::Value),
+        "TIMESTAMP" => get!(chrono::DateTime<chrono::Utc>),
+        "DATETIME" => get!(chrono::NaiveDateTime),
+        "DATE" => get!(chrono::NaiveDate),
+        "TIME" => get!(chrono::NaiveTime),
+        type_name => Err(unsupported_type(type_name, column.name())),
+    }
+}
+
+pub fn parse_sql_to_lua_postgres(lua: &mlua::Lua, row: &PgRow) -> mlua::Result<mlua::Table> {
+    let table = lua.create_table()?;
+    for index in 0..row.len() {
+        table.set(
+            row.column(index).name(),
+            postgres_column_to_lua(lua, row, index)?,
+        )?;
+    }
+
+    Ok(table)
+}
+
+pub fn parse_sql_to_lua_sqlite(lua: &mlua::Lua, row: &SqliteRow) -> mlua::Result<mlua::Table> {
+    let table = lua.create_table()?;
+    for index in 0..row.len() {
+        table.set(
+            row.column(index).name(),
+            sqlite_column_to_lua(lua, row, index)?,
+        )?;
+    }
+
+    Ok(table)
+}
+
+pub fn parse_sql_to_lua_mysql(lua: &mlua::Lua, row: &MySqlRow) -> mlua::Result<mlua::Table> {
+    let table = lua.create_table()?;
+    for index in 0..row.len() {
+        table.set(
+            row.column(index).name(),
+            mysql_column_to_lua(lua, row, index)?,
+        )?;
+    }
+
+    Ok(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::postgres::types::{PgInterval, PgMoney, PgRange, PgTimeTz};
+
+    fn type_info<T: sqlx::Type<Postgres>>() -> sqlx::postgres::PgTypeInfo {
+        T::type_info()
+    }
+
+    #[test]
+    fn reads_text_like_postgres_types_as_strings() {
+        assert!(is_text_like(&type_info::<String>()));
+        assert!(is_text_like(&type_info::<&str>()));
+    }
+
+    #[test]
+    fn refuses_binary_postgres_types_without_a_mapping() {
+        for type_info in [
+            type_info::<PgInterval>(),
+            type_info::<PgMoney>(),
+            type_info::<PgTimeTz>(),
+            type_info::<PgRange<i32>>(),
+            type_info::<PgRange<chrono::NaiveDate>>(),
+            type_info::<Vec<String>>(),
+            type_info::<i32>(),
+            type_info::<Vec<u8>>(),
+        ] {
+            assert!(!is_text_like(&type_info), "{}", type_info.name());
+        }
+
+        let error = unsupported_type("INTERVAL", "duration").to_string();
+        assert!(error.contains("Unsupported column type INTERVAL of the column duration"));
+    }
+}
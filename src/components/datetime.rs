@@ -3,7 +3,7 @@ use mlua::{FromLua, UserData};
 
 #[derive(Debug, Clone, FromLua)]
 pub struct LuaDateTime {
-    dt: DateTime<FixedOffset>,
+    pub dt: DateTime<FixedOffset>,
 }
 impl LuaDateTime {
     pub fn register_to_lua(lua: &mlua::Lua) -> mlua::Result<&'static str> {
@@ -21,19 +21,7 @@ impl LuaDateTime {
 
         lua.globals().set(
             "astra_internal__datetime_new_parse",
-            lua.create_function(|_, date_str: String| {
-                match DateTime::parse_from_rfc2822(&date_str) {
-                    Ok(dt) => Ok(Self { dt }),
-                    Err(err1) => match DateTime::parse_from_rfc3339(&date_str) {
-                        Ok(dt) => Ok(Self { dt }),
-                        Err(err2) => Err(mlua::Error::runtime(format!(
-                            "\nRFC 2822 ERR: {:?}\nRFC 3339 ERR: {:?}",
-                            err1.to_string(),
-                            err2.to_string()
-                        ))),
-                    },
-                }
-            })?,
+            lua.create_function(|_, date_str: String| Self::parse(&date_str))?,
         )?;
 
         lua.globals().set(
@@ -89,6 +77,31 @@ impl LuaDateTime {
     }
 }
 
+impl LuaDateTime {
+    /// Parses an RFC 2822 or RFC 3339 date.
+    pub fn parse(date_str: &str) -> mlua::Result<Self> {
+        match DateTime::parse_from_rfc2822(date_str) {
+            Ok(dt) => Ok(Self { dt }),
+            Err(err1) => match DateTime::parse_from_rfc3339(date_str) {
+                Ok(dt) => Ok(Self { dt }),
+                Err(err2) => Err(mlua::Error::runtime(format!(
+                    "\nRFC 2822 ERR: {:?}\nRFC 3339 ERR: {:?}",
+                    err1.to_string(),
+                    err2.to_string()
+                ))),
+            },
+        }
+    }
+
+    /// Formats with the strftime specifiers, erroring on an invalid one instead of panicking.
+    pub fn format(&self, format: &str) -> mlua::Result<String> {
+        let items = chrono::format::StrftimeItems::new(format)
+            .parse()
+            .map_err(|_| mlua::Error::runtime(format!("Invalid date format: {format}")))?;
+
+        Ok(self.dt.format_with_items(items.iter()).to_string())
+    }
+}
 impl UserData for LuaDateTime {
     fn add_methods<M: mlua::UserDataMethods<Self>>(methods: &mut M) {
         macro_rules! add_getter_method {
@@ -187,8 +200,6 @@ impl UserData for LuaDateTime {
         methods.add_method("to_iso_string", |_, this, ()| {
             Ok(this.dt.to_rfc3339_opts(SecondsFormat::Millis, false))
         });
-        methods.add_method("to_format", |_, this, format: String| {
-            Ok(this.dt.format(&format).to_string())
-        });
+        methods.add_method("to_format", |_, this, format: String| this.format(&format));
     }
 }
@@ -50,8 +50,9 @@ pub fn json_encode(lua: &mlua::Lua) {
 
             for pair in input.pairs::<mlua::Value, mlua::Value>() {
                 let (key, value) = pair?;
+                // the null sentinel is a light userdata too, but is kept as a JSON null
                 if !value.is_function()
-                    && !value.is_light_userdata()
+                    && (!value.is_light_userdata() || value.is_null())
                     && !value.is_userdata()
                     && !value.is_error()
                     && !value.is_thread()
@@ -5,8 +5,15 @@
 ---@field headers fun(): table|nil Returns the entire headers list from the HTTP response
 ---@field remote_address fun(): string|nil Gets the remote address of the HTTP response server
 
+--- Represents a failed HTTP client request.
+---@class HTTPClientError
+---@field kind fun(): "timeout"|"connect"|"dns"|"tls"|"body"|"status"|"redirect"|"request" Gets the kind of the failure
+---@field message fun(): string Gets the full error message
+---@field url fun(): string|nil Gets the URL of the failed request
+---@field status_code fun(): number|nil Gets the HTTP Status code, if the failure was caused by the response status
+
 ---@diagnostic disable-next-line: duplicate-doc-alias
----@alias http_client_callback fun(response: HTTPClientResponse)
+---@alias http_client_callback fun(response: HTTPClientResponse|nil, error: HTTPClientError|nil)
 
 --- Represents an HTTP client request.
 ---@class HTTPClientRequest
@@ -15,10 +22,15 @@
 ---@field set_headers fun(http_request: HTTPClientRequest, headers: table): HTTPClientRequest Sets all of the headers
 ---@field set_form fun(http_request: HTTPClientRequest, key: string, value: string): HTTPClientRequest Sets a form
 ---@field set_forms fun(http_request: HTTPClientRequest, headers: table): HTTPClientRequest Sets all of the forms
+---@field set_query fun(http_request: HTTPClientRequest, key: string, value: string): HTTPClientRequest Appends a URL encoded query parameter
+---@field set_queries fun(http_request: HTTPClientRequest, query: table): HTTPClientRequest Replaces the query parameters, including the ones from set_query, with these sorted by name
+---@field set_basic_auth fun(http_request: HTTPClientRequest, username: string, password: string|nil): HTTPClientRequest Sets the HTTP Basic authentication
+---@field set_bearer_auth fun(http_request: HTTPClientRequest, token: string): HTTPClientRequest Sets the HTTP Bearer authentication
 ---@field set_body fun(http_request: HTTPClientRequest, body: string): HTTPClientRequest Sets the HTTP body
 ---@field set_json fun(http_request: HTTPClientRequest, json: table): HTTPClientRequest Sets the HTTP json
 ---@field set_file fun(http_request: HTTPClientRequest, file_path: string): HTTPClientRequest Sets the for-upload file path
----@field execute fun(): HTTPClientResponse Executes the request and returns the response
+---@field set_error_for_status fun(http_request: HTTPClientRequest, enabled: boolean): HTTPClientRequest Treats 4xx and 5xx responses as errors of kind "status"
+---@field execute fun(): HTTPClientResponse|nil, HTTPClientError|nil Executes the request and returns the response, or nil and the error. It does not raise on a failed request, which can be wrapped with `assert` to raise it
 ---@field execute_task fun(http_request: HTTPClientRequest, callback: http_client_callback) Executes the request as an async task
 
 ---Opens a new async HTTP Request. The request is running as a task in parallel
@@ -30,3 +42,39 @@ function Astra.http.request(url)
 	---@diagnostic disable-next-line: undefined-global
 	return astra_internal__http_request(url)
 end
+
+--- Represents a reusable HTTP client which can optionally keep cookies between requests.
+---@class HTTPClient
+---@field request fun(http_client: HTTPClient, url: string): HTTPClientRequest Opens a new request that runs through this client
+---@field cookies fun(http_client: HTTPClient, url: string): table Returns the stored cookies that would be sent to the URL
+---@field clear_cookies fun(http_client: HTTPClient) Removes every stored cookie
+---@field save_cookies fun(http_client: HTTPClient, file_path: string) Saves the cookies to a JSON file, including the session cookies without an expiry
+---@field load_cookies fun(http_client: HTTPClient, file_path: string) Replaces the stored cookies with the ones from a JSON file
+
+---@class HTTPClientOptions
+---@field cookie_store boolean? Keeps the cookies set by the responses and sends them with the next requests
+
+---Creates a new HTTP client which can be shared between requests
+---@param options HTTPClientOptions?
+---@return HTTPClient
+---@nodiscard
+---@diagnostic disable-next-line: missing-return, lowercase-global
+function Astra.http.client(options)
+	---@diagnostic disable-next-line: undefined-global
+	return astra_internal__http_client(options)
+end
+
+---@class HTTPExecuteAllOptions
+---@field concurrency number? Max number of requests running at the same time, defaults to 8
+
+---Executes many requests concurrently. The results are in the same order as the requests,
+---and each position holds either a response or an error.
+---@param requests HTTPClientRequest[]
+---@param options HTTPExecuteAllOptions?
+---@return (HTTPClientResponse|nil)[] responses
+---@return (HTTPClientError|nil)[] errors
+---@diagnostic disable-next-line: missing-return, lowercase-global
+function Astra.http.execute_all(requests, options)
+	---@diagnostic disable-next-line: undefined-global
+	return astra_internal__http_execute_all(requests, options)
+end
@@ -0,0 +1,89 @@
+use mlua::UserData;
+use std::error::Error;
+
+#[derive(Debug, Clone)]
+pub struct HTTPClientError {
+    /// One of `timeout`, `connect`, `dns`, `tls`, `body`, `status`, `redirect` or `request`
+    pub kind: String,
+    pub message: String,
+    pub url: Option<String>,
+    pub status_code: Option<u16>,
+}
+impl From<reqwest::Error> for HTTPClientError {
+    fn from(error: reqwest::Error) -> Self {
+        // the full chain is needed, as reqwest only tells about the outermost layer
+        let mut message = error.to_string();
+        let mut causes = String::new();
+        let mut is_tls = false;
+        let mut source = error.source();
+        while let Some(inner) = source {
+            message.push_str(&format!(": {inner}"));
+            causes.push_str(&format!("{inner}\n").to_lowercase());
+            is_tls |= is_tls_error(inner);
+            source = inner.source();
+        }
+
+        // reqwest tells apart most of the kinds by itself. For a failed connection, TLS is told
+        // apart by the type of the error and DNS by the causes, leaving out the outermost
+        // message as it holds the URL.
+        let kind = if error.is_timeout() {
+            "timeout"
+        } else if error.is_status() {
+            "status"
+        } else if error.is_body() || error.is_decode() {
+            "body"
+        } else if error.is_redirect() {
+            "redirect"
+        } else if error.is_connect() {
+            if causes.contains("dns error") {
+                "dns"
+            } else if is_tls {
+                "tls"
+            } else {
+                "connect"
+            }
+        } else {
+            "request"
+        };
+
+        Self {
+            kind: kind.to_string(),
+            message,
+            url: error.url().map(|url| url.to_string()),
+            status_code: error.status().map(|status| status.as_u16()),
+        }
+    }
+}
+
+/// rustls hands its errors to hyper inside of IO errors, whose `source` skips the wrapped error.
+fn is_tls_error(error: &(dyn Error + 'static)) -> bool {
+    if error.is::<rustls::Error>() {
+        return true;
+    }
+
+    error
+        .downcast_ref::<std::io::Error>()
+        .and_then(|error| error.get_ref())
+        .is_some_and(|inner| is_tls_error(inner))
+}
+
+impl std::fmt::Display for HTTPClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "HTTP Request did not execute successfully ({}): {}",
+            self.kind, self.message
+        )
+    }
+}
+impl UserData for HTTPClientError {
+    fn add_methods<M: mlua::UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("kind", |_, this, ()| Ok(this.kind.clone()));
+        methods.add_method("message", |_, this, ()| Ok(this.message.clone()));
+        methods.add_method("url", |_, this, ()| Ok(this.url.clone()));
+        methods.add_method("status_code", |_, this, ()| Ok(this.status_code));
+        methods.add_meta_method(mlua::MetaMethod::ToString, |_, this, ()| {
+            Ok(this.to_string())
+        });
+    }
+}
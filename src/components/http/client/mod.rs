@@ -1,7 +1,131 @@
+mod error;
+
 use crate::components::BodyLua;
+pub use error::HTTPClientError;
+use futures::StreamExt;
 use mlua::{LuaSerdeExt, UserData};
 use reqwest::{Client, RequestBuilder};
-use std::collections::HashMap;
+use reqwest_cookie_store::{CookieStore, CookieStoreMutex};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+#[derive(Debug, Clone)]
+pub struct HTTPClient {
+    pub client: Client,
+    pub cookie_store: Option<Arc<CookieStoreMutex>>,
+}
+impl HTTPClient {
+    pub fn register_to_lua(lua: &mlua::Lua) -> mlua::Result<()> {
+        let function = lua.create_function(|_, options: Option<mlua::Table>| {
+            let mut builder = Client::builder();
+            let mut cookie_store = None;
+
+            if let Some(options) = options
+                && let Ok(Some(true)) = options.get::<Option<bool>>("cookie_store")
+            {
+                let store = Arc::new(CookieStoreMutex::new(CookieStore::default()));
+                builder = builder.cookie_provider(store.clone());
+                cookie_store = Some(store);
+            }
+
+            match builder.build() {
+                Ok(client) => Ok(Self {
+                    client,
+                    cookie_store,
+                }),
+                Err(e) => Err(mlua::Error::runtime(format!(
+                    "Could not create the HTTP client: {e}"
+                ))),
+            }
+        })?;
+        lua.globals().set("astra_internal__http_client", function)
+    }
+
+    fn cookie_store(&self) -> mlua::Result<&Arc<CookieStoreMutex>> {
+        self.cookie_store.as_ref().ok_or_else(|| {
+            mlua::Error::runtime("The HTTP client was created without a cookie store")
+        })
+    }
+}
+impl UserData for HTTPClient {
+    fn add_methods<M: mlua::UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("request", |_, this, url: String| {
+            let mut request = HTTPClientRequest::new(url);
+            request.client = Some(this.client.clone());
+
+            Ok(request)
+        });
+
+        methods.add_method("cookies", |_, this, url: String| {
+            let url = reqwest::Url::parse(&url)
+                .map_err(|e| mlua::Error::runtime(format!("Invalid URL: {e}")))?;
+
+            match this.cookie_store()?.lock() {
+                Ok(store) => Ok(store
+                    .matches(&url)
+                    .into_iter()
+                    .map(|cookie| (cookie.name().to_string(), cookie.value().to_string()))
+                    .collect::<HashMap<String, String>>()),
+                Err(e) => Err(mlua::Error::runtime(format!(
+                    "Could not access the cookie store: {e}"
+                ))),
+            }
+        });
+
+        methods.add_method("clear_cookies", |_, this, ()| {
+            match this.cookie_store()?.lock() {
+                Ok(mut store) => {
+                    store.clear();
+
+                    Ok(())
+                }
+                Err(e) => Err(mlua::Error::runtime(format!(
+                    "Could not access the cookie store: {e}"
+                ))),
+            }
+        });
+
+        methods.add_method("save_cookies", |_, this, file_path: String| {
+            let store = this.cookie_store()?;
+            let mut file = std::fs::File::create(&file_path).map_err(|e| {
+                mlua::Error::runtime(format!("Could not create the file {file_path}: {e}"))
+            })?;
+
+            // the session cookies are kept too, as the expired ones are skipped when loading
+            match store.lock() {
+                Ok(store) => cookie_store::serde::json::save_incl_expired_and_nonpersistent(
+                    &store, &mut file,
+                )
+                .map_err(|e| mlua::Error::runtime(format!("Could not save the cookies: {e}"))),
+                Err(e) => Err(mlua::Error::runtime(format!(
+                    "Could not access the cookie store: {e}"
+                ))),
+            }
+        });
+
+        methods.add_method("load_cookies", |_, this, file_path: String| {
+            let store = this.cookie_store()?;
+            let file = std::fs::File::open(&file_path).map_err(|e| {
+                mlua::Error::runtime(format!("Could not open the file {file_path}: {e}"))
+            })?;
+            let loaded = cookie_store::serde::json::load(std::io::BufReader::new(file))
+                .map_err(|e| mlua::Error::runtime(format!("Could not load the cookies: {e}")))?;
+
+            match store.lock() {
+                Ok(mut store) => {
+                    *store = loaded;
+
+                    Ok(())
+                }
+                Err(e) => Err(mlua::Error::runtime(format!(
+                    "Could not access the cookie store: {e}"
+                ))),
+            }
+        });
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct HTTPClientRequest {
@@ -12,31 +136,83 @@ pub struct HTTPClientRequest {
     pub body_json: Option<serde_json::Value>,
     pub body_file: Option<String>,
     pub form: HashMap<String, String>,
+    pub query: Vec<(String, String)>,
+    pub basic_auth: Option<(String, Option<String>)>,
+    pub bearer_auth: Option<String>,
+    pub error_for_status: bool,
+    pub client: Option<Client>,
 }
 impl HTTPClientRequest {
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            method: "GET".to_string(),
+            headers: HashMap::new(),
+            body: None,
+            body_json: None,
+            body_file: None,
+            form: HashMap::new(),
+            query: Vec::new(),
+            basic_auth: None,
+            bearer_auth: None,
+            error_for_status: false,
+            client: None,
+        }
+    }
+
     pub fn register_to_lua(lua: &mlua::Lua) -> mlua::Result<()> {
-        let function = lua.create_function(|_, url: String| {
-            Ok(Self {
-                url,
-                method: "GET".to_string(),
-                headers: HashMap::new(),
-                body: None,
-                body_json: None,
-                body_file: None,
-                form: HashMap::new(),
-            })
-        })?;
-        lua.globals().set("astra_internal__http_request", function)
+        let function = lua.create_function(|_, url: String| Ok(Self::new(url)))?;
+        lua.globals()
+            .set("astra_internal__http_request", function)?;
+
+        let function = lua.create_async_function(
+            |lua, (requests, options): (mlua::Table, Option<mlua::Table>)| async move {
+                let mut concurrency = 8;
+                if let Some(options) = options
+                    && let Ok(Some(new_concurrency)) = options.get::<Option<usize>>("concurrency")
+                {
+                    concurrency = new_concurrency.max(1);
+                }
+
+                let requests = requests
+                    .sequence_values::<mlua::UserDataRef<Self>>()
+                    .map(|request| request.map(|request| request.clone()))
+                    .collect::<mlua::Result<Vec<_>>>()?;
+
+                // requests are driven concurrently and finish in any order,
+                // so the index is kept to put the results back in input order
+                let mut results = futures::stream::iter(requests.into_iter().enumerate())
+                    .map(|(index, request)| async move { (index, request.send().await) })
+                    .buffer_unordered(concurrency)
+                    .collect::<Vec<_>>()
+                    .await;
+                results.sort_by_key(|(index, _)| *index);
+
+                let responses = lua.create_table()?;
+                let errors = lua.create_table()?;
+                for (index, result) in results {
+                    match result {
+                        Ok(response) => responses.set(index + 1, response)?,
+                        Err(e) => errors.set(index + 1, e)?,
+                    }
+                }
+
+                Ok((responses, errors))
+            },
+        )?;
+        lua.globals()
+            .set("astra_internal__http_execute_all", function)
     }
 
     pub async fn request_builder(&self) -> RequestBuilder {
+        let http_client = self.client.clone().unwrap_or_default();
         let mut client = match self.method.to_uppercase().as_str() {
-            "POST" => Client::new().post(&self.url),
-            "PATCH" => Client::new().patch(&self.url),
-            "PUT" => Client::new().put(&self.url),
-            "DELETE" => Client::new().delete(&self.url),
-            "HEAD" => Client::new().head(&self.url),
-            _ => Client::new().get(&self.url),
+            "POST" => http_client.post(&self.url),
+            "PATCH" => http_client.patch(&self.url),
+            "PUT" => http_client.put(&self.url),
+            "DELETE" => http_client.delete(&self.url),
+            "HEAD" => http_client.head(&self.url),
+            _ => http_client.get(&self.url),
         };
 
         client = if let Some(body) = &self.body {
@@ -80,12 +256,31 @@ impl HTTPClientRequest {
             client = client.form(&self.form);
         }
 
+        if !self.query.is_empty() {
+            client = client.query(&self.query);
+        }
+
+        if let Some((username, password)) = &self.basic_auth {
+            client = client.basic_auth(username, password.as_ref());
+        } else if let Some(token) = &self.bearer_auth {
+            client = client.bearer_auth(token);
+        }
+
         client
     }
 
+    pub async fn send(&self) -> Result<HTTPClientResponse, HTTPClientError> {
+        let mut response = self.request_builder().await.send().await?;
+        if self.error_for_status {
+            response = response.error_for_status()?;
+        }
+
+        Self::response_to_http_client_response(response).await
+    }
+
     pub async fn response_to_http_client_response(
         response: reqwest::Response,
-    ) -> HTTPClientResponse {
+    ) -> Result<HTTPClientResponse, HTTPClientError> {
         let url = response.url().to_string();
         let status_code = response.status().as_u16();
         let remote_address = response.remote_addr().map(|i| i.to_string());
@@ -100,19 +295,15 @@ impl HTTPClientRequest {
             })
             .collect::<std::collections::HashMap<String, String>>();
 
-        let body = if let Ok(bytes) = response.bytes().await {
-            BodyLua::new(bytes)
-        } else {
-            BodyLua::new(bytes::Bytes::new())
-        };
+        let body = BodyLua::new(response.bytes().await?);
 
-        HTTPClientResponse {
+        Ok(HTTPClientResponse {
             url,
             status_code,
             remote_address,
             body,
             headers,
-        }
+        })
     }
 }
 impl UserData for HTTPClientRequest {
@@ -155,6 +346,40 @@ impl UserData for HTTPClientRequest {
             Ok(request)
         });
 
+        methods.add_method_mut("set_query", |_, this, (key, value): (String, String)| {
+            let mut request = this.clone();
+            request.query.push((key, value));
+
+            Ok(request)
+        });
+
+        // sorted by name, as the order of a Lua table is not stable between runs
+        methods.add_method_mut("set_queries", |_, this, query: BTreeMap<String, String>| {
+            let mut request = this.clone();
+            request.query = query.into_iter().collect();
+
+            Ok(request)
+        });
+
+        methods.add_method_mut(
+            "set_basic_auth",
+            |_, this, (username, password): (String, Option<String>)| {
+                let mut request = this.clone();
+                request.basic_auth = Some((username, password));
+                request.bearer_auth = None;
+
+                Ok(request)
+            },
+        );
+
+        methods.add_method_mut("set_bearer_auth", |_, this, token: String| {
+            let mut request = this.clone();
+            request.bearer_auth = Some(token);
+            request.basic_auth = None;
+
+            Ok(request)
+        });
+
         methods.add_method_mut("set_body", |_, this, body: String| {
             let mut request = this.clone();
             request.body = Some(body);
@@ -176,13 +401,17 @@ impl UserData for HTTPClientRequest {
             Ok(request)
         });
 
+        methods.add_method_mut("set_error_for_status", |_, this, enabled: bool| {
+            let mut request = this.clone();
+            request.error_for_status = enabled;
+
+            Ok(request)
+        });
+
         methods.add_async_method("execute", |_, this, ()| async move {
-            let request = this.request_builder().await;
-            match request.send().await {
-                Ok(response) => Ok(Self::response_to_http_client_response(response).await),
-                Err(e) => Err(mlua::Error::runtime(format!(
-                    "HTTP Request did not execute successfully: {e}"
-                ))),
+            match this.send().await {
+                Ok(response) => Ok((Some(response), None)),
+                Err(e) => Ok((None, Some(e))),
             }
         });
 
@@ -190,17 +419,15 @@ impl UserData for HTTPClientRequest {
             "execute_task",
             |_, this, callback: mlua::Function| async move {
                 tokio::spawn(async move {
-                    let request = this.request_builder().await;
-                    match request.send().await {
+                    let result = match this.send().await {
                         Ok(response) => {
-                            if let Err(e) = callback
-                                .call::<()>(Self::response_to_http_client_response(response).await)
-                            {
-                                println!("Error running a task: {e}");
-                            }
+                            callback.call::<()>((Some(response), None::<HTTPClientError>))
                         }
-                        Err(e) => eprintln!("HTTP Request did not execute successfully: {e}"),
+                        Err(e) => callback.call::<()>((None::<HTTPClientResponse>, Some(e))),
                     };
+                    if let Err(e) = result {
+                        println!("Error running a task: {e}");
+                    }
                 });
 
                 Ok(())
@@ -227,3 +454,49 @@ impl UserData for HTTPClientResponse {
         methods.add_method("headers", |_, this, ()| Ok(this.headers.clone()));
     }
 }
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saves_and_loads_the_session_cookies() {
+        let lua = mlua::Lua::new();
+        HTTPClient::register_to_lua(&lua).unwrap();
+        let client = lua
+            .load("astra_internal__http_client({ cookie_store = true })")
+            .eval::<mlua::AnyUserData>()
+            .unwrap();
+
+        let url = reqwest::Url::parse("https://example.com/").unwrap();
+        {
+            let client = client.borrow::<HTTPClient>().unwrap();
+            let mut store = client.cookie_store.as_ref().unwrap().lock().unwrap();
+            store.parse("session=abc", &url).unwrap();
+            store.parse("persistent=def; Max-Age=3600", &url).unwrap();
+        }
+
+        let path = std::env::temp_dir().join(format!("astra-cookies-{}.json", std::process::id()));
+        lua.globals().set("client", client).unwrap();
+        lua.globals().set("path", path.to_str().unwrap()).unwrap();
+        let cookies = lua
+            .load(
+                "client:save_cookies(path)
+                client:clear_cookies()
+                client:load_cookies(path)
+                return client:cookies('https://example.com/')",
+            )
+            .eval::<HashMap<String, String>>()
+            .unwrap();
+        let _ = std::fs::remove_file(path);
+
+        assert_eq!(
+            cookies,
+            HashMap::from([
+                ("session".to_string(), "abc".to_string()),
+                ("persistent".to_string(), "def".to_string()),
+            ])
+        );
+    }
+}
@@ -1,5 +1,8 @@
-use crate::components::http::server::cookie::LuaCookie;
-use axum::http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use crate::components::{http::server::cookie::LuaCookie, templates};
+use axum::{
+    body::Body,
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header::CONTENT_TYPE},
+};
 
 // ! Support more cookie types like signed and private
 #[derive(Debug, Clone)]
@@ -8,11 +11,13 @@ pub enum CookieOperation<'a> {
     Remove { key: String },
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct ResponseLua<'a> {
     pub status_code: StatusCode,
     pub headers: HeaderMap,
     pub cookie_operations: Vec<CookieOperation<'a>>,
+    /// The rendered template, used when the route returns nothing
+    pub body: Option<Body>,
 }
 impl Default for ResponseLua<'_> {
     fn default() -> Self {
@@ -20,6 +25,7 @@ impl Default for ResponseLua<'_> {
             status_code: StatusCode::OK,
             headers: HeaderMap::new(),
             cookie_operations: Vec::new(),
+            body: None,
         }
     }
 }
@@ -92,5 +98,53 @@ impl mlua::UserData for ResponseLua<'_> {
 
             Ok(())
         });
+
+        methods.add_method_mut(
+            "render",
+            |lua,
+             this,
+             (engine, name, context, options): (
+                mlua::Value,
+                String,
+                Option<mlua::Table>,
+                Option<mlua::Table>,
+            )| {
+                // the engine from `Astra.new_templating_engine` wraps the userdata
+                let engine = match engine {
+                    mlua::Value::Table(wrapper) => wrapper.get::<mlua::AnyUserData>("engine")?,
+                    mlua::Value::UserData(engine) => engine,
+                    _ => {
+                        return Err(mlua::Error::runtime(
+                            "Could not render the response: expected a templating engine",
+                        ));
+                    }
+                };
+                let (block, stream) = match options {
+                    Some(options) => (
+                        options.get::<Option<String>>("block")?,
+                        options.get::<Option<bool>>("stream")?.unwrap_or(false),
+                    ),
+                    None => (None, false),
+                };
+
+                this.body = Some(templates::render_body(
+                    lua, &engine, &name, context, block, stream,
+                )?);
+                if !this.headers.contains_key(CONTENT_TYPE) {
+                    match HeaderValue::from_str(&templates::content_type(&name)) {
+                        Ok(content_type) => {
+                            this.headers.insert(CONTENT_TYPE, content_type);
+                        }
+                        Err(e) => {
+                            return Err(mlua::Error::runtime(format!(
+                                "Could not set the header (value): {e:#?}"
+                            )));
+                        }
+                    }
+                }
+
+                Ok(())
+            },
+        );
     }
 }
@@ -66,15 +66,18 @@ pub async fn route(
             .call_async::<mlua::Value>((request, response.clone()))
             .await?;
 
+        let mut response_details = response.borrow_mut::<responses::ResponseLua>()?;
         let mut resulting_response = match result {
             mlua::Value::String(plain) => plain.to_string_lossy().into_response(),
             mlua::Value::Table(_) => {
                 axum::Json(lua.from_value::<serde_json::Value>(result.clone())?).into_response()
             }
-            _ => axum::http::StatusCode::OK.into_response(),
+            _ => match response_details.body.take() {
+                Some(body) => body.into_response(),
+                None => axum::http::StatusCode::OK.into_response(),
+            },
         };
 
-        let response_details = response.borrow::<responses::ResponseLua>()?;
         *resulting_response.status_mut() = response_details.status_code;
 
         for (key, value) in response_details.headers.iter() {
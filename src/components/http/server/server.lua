@@ -37,6 +37,12 @@ Astra.http.server = {}
 ---@field remove_header fun(response: HTTPServerResponse, key: string)
 ---@field set_cookie fun(response: HTTPServerResponse, cookie: Cookie)
 ---@field remove_cookie fun(response: HTTPServerResponse, cookie: Cookie)
+---Renders the template as the body of the response when the route returns nothing, setting the content type from its extension
+---@field render fun(response: HTTPServerResponse, engine: TemplateEngine, name: string, context?: table, options?: HTTPRenderOptions)
+
+---@class HTTPRenderOptions
+---@field block string? Renders only the block with the name, for partial updates
+---@field stream boolean? Sends the template as it renders instead of once it is done
 
 ---@class Cookie
 ---@field set_name fun(cookie: Cookie, name: string)
@@ -0,0 +1,28 @@
+---@meta
+
+---@class KVEntry
+---@field key string
+---@field value any
+
+--- A persistent key value store
+---@class KVStore
+---@field get fun(store: KVStore, key: string): any Returns the value, or nil when the key is missing or expired
+---@field set fun(store: KVStore, key: string, value: any, ttl: number|nil) Stores a JSON serializable value, which expires after the TTL in milliseconds if given
+---@field delete fun(store: KVStore, key: string): boolean Removes the key and returns whether it existed
+---@field scan fun(store: KVStore, prefix: string, limit: number|nil): KVEntry[] Lists the entries whose key starts with the prefix, ordered by key
+---@field increment fun(store: KVStore, key: string, by: number|nil): number Atomically adds to the number of the key, starting from 0, and returns the result
+---@field expire fun(store: KVStore, key: string, ttl: number|nil): boolean Changes the TTL of the key in milliseconds, or removes it when nil, and returns whether the key exists
+---@field ttl fun(store: KVStore, key: string): number|nil Returns the milliseconds left before the key expires, or nil when it does not expire
+---@field transaction fun(store: KVStore, callback: fun(store: KVStore): ...): ... Runs the callback in a transaction which is committed when it returns and rolled back when it errors
+---@field close fun(store: KVStore)
+
+Astra.kv = {}
+
+---Opens the key value store at the path, creating the file if needed.
+---@param path string
+---@return KVStore
+---@nodiscard
+function Astra.kv.open(path)
+	---@diagnostic disable-next-line: undefined-global
+	return astra_internal__kv_open(path)
+end
@@ -0,0 +1,510 @@
+use mlua::{LuaSerdeExt, UserData};
+use sqlx::{
+    Pool, Row, Sqlite, SqliteConnection, Transaction,
+    pool::PoolConnection,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions},
+};
+use std::{str::FromStr, sync::Arc, time::Duration};
+use tokio::{
+    sync::{Mutex, MutexGuard},
+    task::AbortHandle,
+};
+
+/// How often the expired keys are removed from the file. Until then they are only hidden.
+const PURGE_INTERVAL: Duration = Duration::from_secs(60);
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+fn kv_error(action: &str, e: sqlx::Error) -> mlua::Error {
+    mlua::Error::runtime(format!("Error {action} the key value store: {e:#?}"))
+}
+
+fn finished() -> mlua::Error {
+    mlua::Error::runtime("The transaction is already finished")
+}
+
+/// Either a connection of the pool, or the connection of the running transaction.
+enum KVConnection<'a> {
+    Pool(PoolConnection<Sqlite>),
+    Transaction(MutexGuard<'a, Option<Transaction<'static, Sqlite>>>),
+}
+impl KVConnection<'_> {
+    fn get(&mut self) -> mlua::Result<&mut SqliteConnection> {
+        match self {
+            KVConnection::Pool(connection) => Ok(&mut **connection),
+            KVConnection::Transaction(transaction) => transaction
+                .as_mut()
+                .map(|transaction| &mut **transaction)
+                .ok_or_else(finished),
+        }
+    }
+}
+
+/// Stops the task removing the expired keys once the last handle of the store is dropped, so
+/// that the pool and its connections are not kept open by the task alone.
+#[derive(Debug)]
+struct PurgeTask(AbortHandle);
+impl Drop for PurgeTask {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// A persistent key value store kept in a SQLite file. The values are stored as JSON, and
+/// every operation is a single statement, so it is atomic on its own.
+#[derive(Debug, Clone)]
+pub struct KVStore {
+    pub pool: Pool<Sqlite>,
+    /// Set on the handle given to the callback of `transaction`
+    pub transaction: Option<Arc<Mutex<Option<Transaction<'static, Sqlite>>>>>,
+    purge: Arc<PurgeTask>,
+}
+impl KVStore {
+    pub fn register_to_lua(lua: &mlua::Lua) -> mlua::Result<&'static str> {
+        let function =
+            lua.create_async_function(|_, path: String| async move { Self::open(&path).await })?;
+        lua.globals().set("astra_internal__kv_open", function)?;
+
+        Ok(include_str!("kv.lua"))
+    }
+
+    pub async fn open(path: &str) -> mlua::Result<Self> {
+        let options = SqliteConnectOptions::from_str(&format!("sqlite:{path}"))
+            .map_err(|e| mlua::Error::runtime(format!("Invalid key value store path: {e}")))?
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .busy_timeout(Duration::from_secs(10));
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(4)
+            .connect_with(options)
+            .await
+            .map_err(|e| kv_error("opening", e))?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS astra_kv (
+                key TEXT PRIMARY KEY NOT NULL,
+                value TEXT NOT NULL,
+                expires_at INTEGER
+            )",
+        )
+        .execute(&pool)
+        .await
+        .map_err(|e| kv_error("creating", e))?;
+
+        let purge_pool = pool.clone();
+        let purge = tokio::spawn(async move {
+            while !purge_pool.is_closed() {
+                let _ = sqlx::query("DELETE FROM astra_kv WHERE expires_at <= $1")
+                    .bind(now())
+                    .execute(&purge_pool)
+                    .await;
+                tokio::time::sleep(PURGE_INTERVAL).await;
+            }
+        });
+
+        Ok(Self {
+            pool,
+            transaction: None,
+            purge: Arc::new(PurgeTask(purge.abort_handle())),
+        })
+    }
+
+    async fn connection(&self) -> mlua::Result<KVConnection<'_>> {
+        match &self.transaction {
+            Some(transaction) => Ok(KVConnection::Transaction(transaction.lock().await)),
+            None => match self.pool.acquire().await {
+                Ok(connection) => Ok(KVConnection::Pool(connection)),
+                Err(e) => Err(kv_error("connecting to", e)),
+            },
+        }
+    }
+
+    async fn get(&self, lua: &mlua::Lua, key: &str) -> mlua::Result<mlua::Value> {
+        let mut connection = self.connection().await?;
+        let row = sqlx::query(
+            "SELECT value FROM astra_kv WHERE key = $1 AND (expires_at IS NULL OR expires_at > $2)",
+        )
+        .bind(key)
+        .bind(now())
+        .fetch_optional(connection.get()?)
+        .await
+        .map_err(|e| kv_error("reading", e))?;
+
+        match row {
+            Some(row) => decode(lua, row.get::<String, _>("value")),
+            None => Ok(mlua::Value::Nil),
+        }
+    }
+
+    async fn set(&self, key: &str, value: String, ttl: Option<u64>) -> mlua::Result<()> {
+        let mut connection = self.connection().await?;
+        sqlx::query(
+            "INSERT INTO astra_kv (key, value, expires_at) VALUES ($1, $2, $3)
+            ON CONFLICT (key) DO UPDATE SET value = excluded.value, expires_at = excluded.expires_at",
+        )
+        .bind(key)
+        .bind(value)
+        .bind(ttl.map(|ttl| now() + ttl as i64))
+        .execute(connection.get()?)
+        .await
+        .map_err(|e| kv_error("writing", e))?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> mlua::Result<bool> {
+        let mut connection = self.connection().await?;
+        let result = sqlx::query(
+            "DELETE FROM astra_kv WHERE key = $1 AND (expires_at IS NULL OR expires_at > $2)",
+        )
+        .bind(key)
+        .bind(now())
+        .execute(connection.get()?)
+        .await
+        .map_err(|e| kv_error("writing", e))?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn scan(
+        &self,
+        lua: &mlua::Lua,
+        prefix: &str,
+        limit: Option<i64>,
+    ) -> mlua::Result<mlua::Table> {
+        let mut connection = self.connection().await?;
+        let rows = sqlx::query(
+            "SELECT key, value FROM astra_kv
+            WHERE key >= $1 AND substr(key, 1, length($1)) = $1
+            AND (expires_at IS NULL OR expires_at > $2)
+            ORDER BY key LIMIT $3",
+        )
+        .bind(prefix)
+        .bind(now())
+        .bind(limit.unwrap_or(-1))
+        .fetch_all(connection.get()?)
+        .await
+        .map_err(|e| kv_error("reading", e))?;
+
+        let entries = lua.create_table()?;
+        for row in rows {
+            let entry = lua.create_table()?;
+            entry.set("key", row.get::<String, _>("key"))?;
+            entry.set("value", decode(lua, row.get::<String, _>("value"))?)?;
+            entries.push(entry)?;
+        }
+
+        Ok(entries)
+    }
+
+    /// Adds to the number of the key, starting from 0 when it is missing or expired. The
+    /// update is skipped when the current value is not a number, which returns no row.
+    async fn increment(
+        &self,
+        lua: &mlua::Lua,
+        key: &str,
+        by: mlua::Value,
+    ) -> mlua::Result<mlua::Value> {
+        let mut connection = self.connection().await?;
+        let query = sqlx::query(
+            "INSERT INTO astra_kv (key, value, expires_at) VALUES ($1, $2, NULL)
+            ON CONFLICT (key) DO UPDATE SET
+                value = CASE WHEN astra_kv.expires_at <= $3 THEN excluded.value
+                    ELSE astra_kv.value + excluded.value END,
+                expires_at = CASE WHEN astra_kv.expires_at <= $3 THEN NULL
+                    ELSE astra_kv.expires_at END
+            WHERE astra_kv.expires_at <= $3 OR json_type(astra_kv.value) IN ('integer', 'real')
+            RETURNING value",
+        )
+        .bind(key);
+        let query = match by {
+            mlua::Value::Nil => query.bind(1),
+            mlua::Value::Integer(by) => query.bind(by),
+            mlua::Value::Number(by) => query.bind(by),
+            by => {
+                return Err(mlua::Error::runtime(format!(
+                    "Can not increment by a {}",
+                    by.type_name()
+                )));
+            }
+        };
+
+        match query
+            .bind(now())
+            .fetch_optional(connection.get()?)
+            .await
+            .map_err(|e| kv_error("writing", e))?
+        {
+            Some(row) => decode(lua, row.get::<String, _>("value")),
+            None => Err(mlua::Error::runtime(format!(
+                "The value of {key} is not a number"
+            ))),
+        }
+    }
+
+    async fn expire(&self, key: &str, ttl: Option<u64>) -> mlua::Result<bool> {
+        let mut connection = self.connection().await?;
+        let result = sqlx::query(
+            "UPDATE astra_kv SET expires_at = $2
+            WHERE key = $1 AND (expires_at IS NULL OR expires_at > $3)",
+        )
+        .bind(key)
+        .bind(ttl.map(|ttl| now() + ttl as i64))
+        .bind(now())
+        .execute(connection.get()?)
+        .await
+        .map_err(|e| kv_error("writing", e))?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn ttl(&self, key: &str) -> mlua::Result<Option<i64>> {
+        let mut connection = self.connection().await?;
+        let now = now();
+        let row = sqlx::query("SELECT expires_at FROM astra_kv WHERE key = $1 AND expires_at > $2")
+            .bind(key)
+            .bind(now)
+            .fetch_optional(connection.get()?)
+            .await
+            .map_err(|e| kv_error("reading", e))?;
+
+        Ok(row.map(|row| row.get::<i64, _>("expires_at") - now))
+    }
+}
+
+fn decode(lua: &mlua::Lua, value: String) -> mlua::Result<mlua::Value> {
+    match serde_json::from_str::<serde_json::Value>(&value) {
+        Ok(value) => lua.to_value(&value),
+        Err(e) => Err(mlua::Error::runtime(format!(
+            "Could not decode the stored value: {e}"
+        ))),
+    }
+}
+
+fn encode(lua: &mlua::Lua, value: mlua::Value) -> mlua::Result<String> {
+    if value.is_nil() {
+        return Err(mlua::Error::runtime(
+            "Can not store nil, use delete to remove the key",
+        ));
+    }
+
+    match serde_json::to_string(&lua.from_value::<serde_json::Value>(value)?) {
+        Ok(value) => Ok(value),
+        Err(e) => Err(mlua::Error::runtime(format!(
+            "Could not encode the value: {e}"
+        ))),
+    }
+}
+
+impl UserData for KVStore {
+    fn add_methods<M: mlua::UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_async_method("get", |lua, this, key: String| async move {
+            this.get(&lua, &key).await
+        });
+
+        methods.add_async_method(
+            "set",
+            |lua, this, (key, value, ttl): (String, mlua::Value, Option<u64>)| async move {
+                this.set(&key, encode(&lua, value)?, ttl).await
+            },
+        );
+
+        methods.add_async_method("delete", |_, this, key: String| async move {
+            this.delete(&key).await
+        });
+
+        methods.add_async_method(
+            "scan",
+            |lua, this, (prefix, limit): (String, Option<i64>)| async move {
+                this.scan(&lua, &prefix, limit).await
+            },
+        );
+
+        methods.add_async_method(
+            "increment",
+            |lua, this, (key, by): (String, mlua::Value)| async move {
+                this.increment(&lua, &key, by).await
+            },
+        );
+
+        methods.add_async_method(
+            "expire",
+            |_, this, (key, ttl): (String, Option<u64>)| async move { this.expire(&key, ttl).await },
+        );
+
+        methods.add_async_method(
+            "ttl",
+            |_, this, key: String| async move { this.ttl(&key).await },
+        );
+
+        methods.add_async_method(
+            "transaction",
+            |_, this, callback: mlua::Function| async move {
+                if this.transaction.is_some() {
+                    return Err(mlua::Error::runtime("The transaction is already running"));
+                }
+
+                // taking the write lock right away avoids failing on a busy database halfway
+                let transaction = match this.pool.begin_with("BEGIN IMMEDIATE").await {
+                    Ok(transaction) => Arc::new(Mutex::new(Some(transaction))),
+                    Err(e) => return Err(kv_error("starting a transaction on", e)),
+                };
+                let store = KVStore {
+                    pool: this.pool.clone(),
+                    transaction: Some(transaction.clone()),
+                    purge: this.purge.clone(),
+                };
+
+                let result = callback.call_async::<mlua::MultiValue>(store).await;
+                let Some(transaction) = transaction.lock().await.take() else {
+                    return Err(finished());
+                };
+
+                match result {
+                    Ok(result) => match transaction.commit().await {
+                        Ok(()) => Ok(result),
+                        Err(e) => Err(kv_error("committing to", e)),
+                    },
+                    Err(e) => {
+                        transaction
+                            .rollback()
+                            .await
+                            .map_err(|e| kv_error("rolling back", e))?;
+                        Err(e)
+                    }
+                }
+            },
+        );
+
+        methods.add_async_method("close", |_, this, ()| async move {
+            this.pool.close().await;
+            Ok(())
+        });
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    async fn lua_with_store(name: &str) -> mlua::Lua {
+        let path = std::env::temp_dir().join(format!("astra-kv-{name}-{}.db", std::process::id()));
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+
+        let lua = mlua::Lua::new();
+        let store = KVStore::open(path.to_str().unwrap()).await.unwrap();
+        lua.globals().set("store", store).unwrap();
+        lua
+    }
+
+    async fn run(lua: &mlua::Lua, source: &str) {
+        lua.load(source).exec_async().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn sets_and_gets_values() {
+        let lua = lua_with_store("values").await;
+        run(
+            &lua,
+            "store:set('user:1', { name = 'Tom', tags = { 'a', 'b' } })
+            store:set('user:2', 'Jerry')
+            assert(store:get('user:1').name == 'Tom')
+            assert(store:get('user:1').tags[2] == 'b')
+            assert(store:get('user:2') == 'Jerry')
+            assert(store:get('missing') == nil)
+            assert(#store:scan('user:') == 2)
+            assert(store:delete('user:2'))
+            assert(not store:delete('user:2'))
+            assert(not pcall(store.set, store, 'nothing', nil))",
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn hides_the_expired_keys() {
+        let lua = lua_with_store("expiry").await;
+        run(
+            &lua,
+            "store:set('short', 1, 20)
+            store:set('long', 2, 60000)
+            store:set('forever', 3)
+            assert(store:ttl('long') > 59000)
+            assert(store:ttl('forever') == nil)
+            assert(store:expire('forever', 20))",
+        )
+        .await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        run(
+            &lua,
+            "assert(store:get('short') == nil)
+            assert(store:get('forever') == nil)
+            assert(store:get('long') == 2)
+            assert(store:ttl('short') == nil)
+            assert(not store:expire('short', 1000))
+            assert(#store:scan('') == 1)",
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn increments_numbers() {
+        let lua = lua_with_store("increment").await;
+        run(
+            &lua,
+            "assert(store:increment('hits') == 1)
+            assert(store:increment('hits', 5) == 6)
+            assert(store:increment('hits', 0.5) == 6.5)
+            store:set('name', 'Tom')
+            assert(not pcall(store.increment, store, 'name'))
+            assert(store:get('name') == 'Tom')
+            assert(not pcall(store.increment, store, 'hits', 'one'))",
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn commits_and_rolls_back_transactions() {
+        let lua = lua_with_store("transaction").await;
+        run(
+            &lua,
+            "local result = store:transaction(function(tx)
+                tx:set('a', 1)
+                tx:increment('a')
+                return tx:get('a')
+            end)
+            assert(result == 2)
+            assert(store:get('a') == 2)
+
+            local ok = pcall(store.transaction, store, function(tx)
+                tx:set('a', 10)
+                tx:set('b', 1)
+                error('failed')
+            end)
+            assert(not ok)
+            assert(store:get('a') == 2)
+            assert(store:get('b') == nil)",
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn stops_purging_once_the_store_is_dropped() {
+        let lua = lua_with_store("purge").await;
+        let store = lua.globals().get::<mlua::AnyUserData>("store").unwrap();
+        let purge = store.borrow::<KVStore>().unwrap().purge.0.clone();
+        assert!(!purge.is_finished());
+
+        drop(store);
+        lua.globals().set("store", mlua::Value::Nil).unwrap();
+        lua.gc_collect().unwrap();
+        lua.gc_collect().unwrap();
+        tokio::task::yield_now().await;
+        assert!(purge.is_finished());
+    }
+}
@@ -1,10 +1,12 @@
 use mlua::LuaSerdeExt;
+mod cache;
 mod crypto;
-mod database;
+pub mod database;
 mod datetime;
 pub mod global;
 pub mod http;
 mod io;
+mod kv;
 mod regex;
 mod templates;
 #[cfg(unix)]
@@ -16,7 +18,10 @@ pub async fn register_components(lua: &mlua::Lua) -> mlua::Result<Vec<(String, S
     let global = global::register_to_lua(lua);
     http::server::register_to_lua(lua)?;
     http::client::HTTPClientRequest::register_to_lua(lua)?;
+    http::client::HTTPClient::register_to_lua(lua)?;
     let database = database::Database::register_to_lua(lua)?;
+    let kv = kv::KVStore::register_to_lua(lua)?;
+    let cache = cache::LuaCache::register_to_lua(lua)?;
     let datetime = datetime::LuaDateTime::register_to_lua(lua)?;
     let crypto = crypto::register_to_lua(lua)?;
     let fileio = io::register_to_lua(lua)?;
@@ -27,6 +32,8 @@ pub async fn register_components(lua: &mlua::Lua) -> mlua::Result<Vec<(String, S
         ("global.lua".to_string(), global.to_string()),
         ("http.lua".to_string(), http::type_definitions()),
         ("database.lua".to_string(), database.to_string()),
+        ("kv.lua".to_string(), kv.to_string()),
+        ("cache.lua".to_string(), cache.to_string()),
         ("crypto.lua".to_string(), crypto.to_string()),
         ("io.lua".to_string(), fileio.to_string()),
         ("templates.lua".to_string(), templates.to_string()),
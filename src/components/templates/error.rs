@@ -0,0 +1,167 @@
+use mlua::LuaSerdeExt;
+use std::fmt;
+
+/// A template error with where it happened, given to Lua as a table.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct TemplateError {
+    pub kind: String,
+    pub name: Option<String>,
+    pub line: Option<usize>,
+    pub detail: Option<String>,
+    /// The lines around the error, with the failing expression underlined when known
+    pub snippet: Option<String>,
+    pub message: String,
+}
+impl TemplateError {
+    /// The source is used when the error does not carry the one of the template.
+    pub fn new(error: &minijinja::Error, source: Option<&str>) -> Self {
+        let mut detail = error.detail().map(str::to_string);
+        // errors raised by filters and functions keep the cause as their source
+        if let Some(cause) = std::error::Error::source(error) {
+            detail = Some(match detail {
+                Some(detail) => format!("{detail}: {cause}"),
+                None => cause.to_string(),
+            });
+        }
+
+        let source = error.template_source().or(source);
+        let snippet = match (source, error.line()) {
+            (Some(source), Some(line)) => Some(snippet(source, line, error.range())),
+            _ => None,
+        };
+
+        let mut template_error = Self {
+            kind: error.kind().to_string(),
+            name: error.name().map(str::to_string),
+            line: error.line(),
+            detail,
+            snippet,
+            message: String::new(),
+        };
+        template_error.message = template_error.to_string();
+
+        template_error
+    }
+
+    /// Tera keeps the cause of its errors as their source, and puts the position of syntax
+    /// errors in their message along with a snippet.
+    pub fn from_tera(error: &tera::Error, name: Option<&str>) -> Self {
+        let kind = match &error.kind {
+            tera::ErrorKind::Msg(message) if message.starts_with("Failed to parse") => {
+                "syntax error"
+            }
+            tera::ErrorKind::TemplateNotFound(_) => "template not found",
+            tera::ErrorKind::MissingParent { .. } | tera::ErrorKind::CircularExtend { .. } => {
+                "invalid inheritance"
+            }
+            _ => "render error",
+        };
+
+        let mut causes = Vec::new();
+        let mut source = std::error::Error::source(error);
+        while let Some(cause) = source {
+            causes.push(cause.to_string());
+            source = cause.source();
+        }
+        let detail = match causes.is_empty() {
+            true => error.to_string(),
+            false => causes.join(": ").trim().to_string(),
+        };
+        // the templates compiled together are named in the message, as in `Failed to parse "a.html"`
+        let name = name.map(str::to_string).or_else(|| match &error.kind {
+            tera::ErrorKind::Msg(message) => message
+                .strip_prefix("Failed to parse ")
+                .map(|name| name.trim_matches(['"', '\'']).to_string()),
+            _ => None,
+        });
+        let line = detail.split_once("--> ").and_then(|(_, position)| {
+            position
+                .split(':')
+                .next()
+                .and_then(|line| line.trim().parse().ok())
+        });
+
+        let mut template_error = Self {
+            kind: kind.to_string(),
+            name,
+            line,
+            detail: Some(detail),
+            snippet: None,
+            message: String::new(),
+        };
+        template_error.message = template_error.to_string();
+
+        template_error
+    }
+}
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TEMPLATING ERROR - {}", self.kind)?;
+        if let Some(name) = &self.name {
+            write!(f, " in {name}")?;
+        }
+        if let Some(line) = self.line {
+            write!(f, " at line {line}")?;
+        }
+        if let Some(detail) = &self.detail {
+            write!(f, ": {detail}")?;
+        }
+        if let Some(snippet) = &self.snippet {
+            write!(f, "\n{snippet}")?;
+        }
+
+        Ok(())
+    }
+}
+impl std::error::Error for TemplateError {}
+impl mlua::IntoLua for TemplateError {
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        lua.to_value_with(
+            &self,
+            mlua::SerializeOptions::new().serialize_none_to_null(false),
+        )
+    }
+}
+
+/// Shows the line of the error between the ones around it, underlining the byte range when
+/// it falls within that line.
+fn snippet(source: &str, line: usize, range: Option<std::ops::Range<usize>>) -> String {
+    let lines = source.lines().collect::<Vec<_>>();
+    let first = line.saturating_sub(1).max(1);
+    let last = (line + 1).min(lines.len());
+    let width = last.to_string().len();
+
+    let mut snippet = Vec::new();
+    for number in first..=last {
+        let Some(content) = lines.get(number - 1) else {
+            break;
+        };
+        let marker = if number == line { '>' } else { ' ' };
+        snippet.push(format!("{marker} {number:>width$} | {content}"));
+
+        if number != line {
+            continue;
+        }
+        let line_start = lines[..number - 1]
+            .iter()
+            .map(|line| line.len() + 1)
+            .sum::<usize>();
+        let underline = range.as_ref().and_then(|range| {
+            let start = range.start.checked_sub(line_start)?;
+            let end = range.end.saturating_sub(line_start).min(content.len());
+            Some((content.get(..start)?, content.get(start..end)?))
+        });
+        if let Some((before, underlined)) = underline {
+            let start = before.chars().count();
+            let length = underlined.chars().count().max(1);
+            snippet.push(format!(
+                "  {:width$} | {}{}",
+                "",
+                " ".repeat(start),
+                "^".repeat(length)
+            ));
+        }
+    }
+
+    snippet.join("\n")
+}
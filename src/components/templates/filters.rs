@@ -0,0 +1,197 @@
+use super::{markdown, to_template_value};
+use crate::{LUA, components::datetime::LuaDateTime};
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use minijinja::{Error, ErrorKind, Value};
+use mlua::LuaSerdeExt;
+
+pub fn add_builtin_filters(env: &mut minijinja::Environment<'_>) {
+    env.add_filter("date", date);
+    env.add_filter("json", json);
+    env.add_filter("markdown", |source: String| {
+        Value::from_safe_string(markdown::to_html(&source))
+    });
+    env.add_filter("slugify", |text: String| slug::slugify(text));
+}
+
+/// Formats a date given as an RFC 3339 or RFC 2822 string, a datetime object, or epoch
+/// milliseconds. Dates without a timezone are taken as UTC.
+fn date(value: Value, format: Option<String>) -> Result<String, Error> {
+    let datetime = if let Some(milliseconds) = value.as_i64() {
+        DateTime::from_timestamp_millis(milliseconds).map(|dt| LuaDateTime {
+            dt: dt.fixed_offset(),
+        })
+    } else if let Some(text) = value.as_str() {
+        LuaDateTime::parse(text).ok().or_else(|| {
+            NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S")
+                .or_else(|_| NaiveDate::parse_from_str(text, "%Y-%m-%d").map(|date| date.into()))
+                .ok()
+                .map(|dt| LuaDateTime {
+                    dt: dt.and_utc().fixed_offset(),
+                })
+        })
+    } else {
+        None
+    };
+
+    let Some(datetime) = datetime else {
+        return Err(Error::new(
+            ErrorKind::InvalidOperation,
+            format!("Could not read the date from {value}"),
+        ));
+    };
+
+    datetime
+        .format(format.as_deref().unwrap_or("%Y-%m-%d"))
+        .map_err(|e| Error::new(ErrorKind::InvalidOperation, e.to_string()))
+}
+
+/// Serializes into JSON that is safe to embed in HTML, including within script tags.
+fn json(value: Value, pretty: Option<bool>) -> Result<Value, Error> {
+    let json = if pretty.unwrap_or(false) {
+        serde_json::to_string_pretty(&value)
+    } else {
+        serde_json::to_string(&value)
+    }
+    .map_err(|e| {
+        Error::new(
+            ErrorKind::InvalidOperation,
+            format!("Could not serialize into JSON: {e}"),
+        )
+    })?;
+
+    Ok(Value::from_safe_string(
+        json.replace('<', "\\u003c")
+            .replace('>', "\\u003e")
+            .replace('&', "\\u0026")
+            .replace('\'', "\\u0027"),
+    ))
+}
+
+/// Calls the Lua function with the positional arguments in order, followed by a table of the
+/// keyword arguments if there are any.
+fn call_lua(function: &mlua::Function, args: &[Value]) -> Result<mlua::Value, Error> {
+    let args = args
+        .iter()
+        .map(|arg| LUA.to_value(arg))
+        .collect::<mlua::Result<mlua::MultiValue>>()
+        .map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidOperation,
+                format!("Could not convert the arguments into Lua: {e}"),
+            )
+        })?;
+
+    // called synchronously, as blocking on an async call would park the thread of the runtime
+    function
+        .call::<mlua::Value>(args)
+        .map_err(|e| Error::new(ErrorKind::InvalidOperation, e.to_string()))
+}
+
+pub fn lua_filter(
+    function: mlua::Function,
+) -> impl Fn(Value, &[Value]) -> Result<Value, Error> + Send + Sync + 'static {
+    move |value: Value, args: &[Value]| {
+        let args = std::iter::once(value)
+            .chain(args.iter().cloned())
+            .collect::<Vec<_>>();
+
+        to_template_value(&LUA, call_lua(&function, &args)?).map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidOperation,
+                format!("Could not convert the return value: {e}"),
+            )
+        })
+    }
+}
+
+pub fn lua_test(
+    function: mlua::Function,
+) -> impl Fn(Value, &[Value]) -> Result<bool, Error> + Send + Sync + 'static {
+    move |value: Value, args: &[Value]| {
+        let args = std::iter::once(value)
+            .chain(args.iter().cloned())
+            .collect::<Vec<_>>();
+
+        Ok(!matches!(
+            call_lua(&function, &args)?,
+            mlua::Value::Nil | mlua::Value::Boolean(false)
+        ))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use std::{sync::Arc, time::Duration};
+
+    fn environment() -> minijinja::Environment<'static> {
+        let shout = LUA
+            .load("return function(value, suffix) return value:upper() .. suffix end")
+            .eval::<mlua::Function>()
+            .unwrap();
+        let short = LUA
+            .load("return function(value, length) return #value <= length end")
+            .eval::<mlua::Function>()
+            .unwrap();
+
+        let mut env = minijinja::Environment::new();
+        env.add_filter("shout", lua_filter(shout));
+        env.add_test("short", lua_test(short));
+        env.add_template("page", "{{ name | shout('!') }} {{ name is short(5) }}")
+            .unwrap();
+        env
+    }
+
+    // a single worker, which a blocked filter would leave with nothing to run the test on
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn runs_lua_filters_inside_the_runtime() {
+        let env = Arc::new(environment());
+        let render = |env: &minijinja::Environment| {
+            env.get_template("page")
+                .unwrap()
+                .render(minijinja::context! { name => "astra" })
+                .unwrap()
+        };
+
+        assert_eq!(render(&env), "ASTRA! true");
+
+        // as the streamed rendering does
+        let blocking_env = env.clone();
+        let rendered = tokio::time::timeout(
+            Duration::from_secs(5),
+            tokio::task::spawn_blocking(move || render(&blocking_env)),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(rendered, "ASTRA! true");
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn refuses_async_lua_in_filters() {
+        let sleep = LUA
+            .create_async_function(|_, ()| async {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                Ok(())
+            })
+            .unwrap();
+        let waiting = LUA
+            .load("local sleep = ...; return function(value) sleep(); return value end")
+            .call::<mlua::Function>(sleep)
+            .unwrap();
+
+        let mut env = minijinja::Environment::new();
+        env.add_filter("waiting", lua_filter(waiting));
+        env.add_template("page", "{{ 1 | waiting }}").unwrap();
+
+        let result = tokio::time::timeout(
+            Duration::from_secs(5),
+            tokio::task::spawn_blocking(move || env.get_template("page").unwrap().render(())),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,142 @@
+//! Renders Markdown for the `markdown` filter with CommonMark, along with tables and
+//! strikethrough. As the output is marked safe, raw HTML is escaped instead of being passed
+//! through, and the links and images keep only the URLs which can not run scripts.
+
+use pulldown_cmark::{CowStr, Event, Options, Parser, Tag};
+
+pub fn to_html(source: &str) -> String {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH;
+    let events = Parser::new_ext(source, options).map(|event| match event {
+        Event::Html(html) | Event::InlineHtml(html) => Event::Text(html),
+        Event::Start(Tag::Link {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) => Event::Start(Tag::Link {
+            link_type,
+            dest_url: safe_url(&dest_url),
+            title,
+            id,
+        }),
+        Event::Start(Tag::Image {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) => Event::Start(Tag::Image {
+            link_type,
+            dest_url: safe_url(&dest_url),
+            title,
+            id,
+        }),
+        event => event,
+    });
+
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, events);
+
+    html
+}
+
+/// Allows relative URLs and the http, https and mailto schemes, replacing the others with `#`.
+/// The control characters and whitespace which browsers ignore are removed first, so that they
+/// can not hide a scheme, as in `\u{1}java\tscript:`.
+fn safe_url(url: &str) -> CowStr<'static> {
+    let url = url
+        .chars()
+        .filter(|c| !c.is_control() && !c.is_whitespace())
+        .collect::<String>();
+
+    // a colon before any slash, query or fragment starts a scheme
+    let scheme = url
+        .find([':', '/', '?', '#'])
+        .filter(|index| url[*index..].starts_with(':'))
+        .map(|index| url[..index].to_ascii_lowercase());
+
+    match scheme.as_deref() {
+        None | Some("http" | "https" | "mailto") => CowStr::from(url),
+        Some(_) => CowStr::Borrowed("#"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_markdown() {
+        assert_eq!(
+            to_html("# Title\n\nSome *emphasis* and `code`"),
+            "<h1>Title</h1>\n<p>Some <em>emphasis</em> and <code>code</code></p>\n"
+        );
+        assert_eq!(
+            to_html("[docs](https://example.com/a_(b))"),
+            "<p><a href=\"https://example.com/a_(b)\">docs</a></p>\n"
+        );
+    }
+
+    #[test]
+    fn escapes_raw_html() {
+        let html = to_html("<script>alert(1)</script>\n\nHi <img src=x onerror=alert(1)>");
+        assert!(!html.contains("<script>"));
+        assert!(!html.contains("<img"));
+        assert!(html.contains("&lt;script&gt;"));
+    }
+
+    #[test]
+    fn allows_safe_urls() {
+        for url in [
+            "https://example.com",
+            "http://example.com/a?b=c#d",
+            "mailto:someone@example.com",
+            "/relative/path",
+            "page.html",
+            "#fragment",
+            "?query=1",
+            "//example.com/path",
+            "path/with:colon",
+        ] {
+            assert_eq!(&*safe_url(url), url);
+        }
+    }
+
+    #[test]
+    fn blocks_script_urls() {
+        for url in [
+            "javascript:alert(1)",
+            "JaVaScRiPt:alert(1)",
+            "\u{1}javascript:alert(1)",
+            " \u{0}\u{1f}javascript:alert(1)",
+            "java\tscript:alert(1)",
+            "java\nscript:alert(1)",
+            "vbscript:msgbox(1)",
+            "data:text/html;base64,PHNjcmlwdD5hbGVydCgxKTwvc2NyaXB0Pg==",
+            "data:image/svg+xml,<svg onload=alert(1)>",
+            "file:///etc/passwd",
+        ] {
+            assert_eq!(&*safe_url(url), "#", "{url:?}");
+        }
+    }
+
+    #[test]
+    fn blocks_script_urls_in_links_and_images() {
+        for source in [
+            "[x](javascript:alert(1))",
+            "[x](\u{1}javascript:alert(1))",
+            "[x](&#106;avascript:alert(1))",
+            "[x](<java\tscript:alert(1)>)",
+            "<javascript:alert(1)>",
+            "[x][ref]\n\n[ref]: javascript:alert(1)",
+            "![x](data:image/svg+xml,alert)",
+        ] {
+            let html = to_html(source);
+            for attribute in ["href=\"", "src=\""] {
+                for (index, _) in html.match_indices(attribute) {
+                    let value = &html[index + attribute.len()..];
+                    assert!(value.starts_with("#\""), "{source:?}: {html}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,743 @@
+use crate::{
+    LUA,
+    components::{datetime::LuaDateTime, global::TaskHandler},
+};
+use error::TemplateError;
+use minijinja::{AutoEscape, ErrorKind::UndefinedError};
+use mlua::{ExternalError, FromLua, LuaSerdeExt, UserData};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+mod error;
+mod filters;
+mod markdown;
+mod tera;
+mod watcher;
+
+pub use tera::TeraEngine;
+
+/// Will include the name, path, and source
+#[derive(Debug, Clone, FromLua)]
+struct Template {
+    name: String,
+    path: Option<String>,
+    source: String,
+}
+
+#[derive(Debug, Clone, FromLua)]
+pub struct TemplatingEngine<'a> {
+    pub env: minijinja::Environment<'a>,
+    templates: Vec<Template>,
+    pub exclusions: Vec<Arc<str>>,
+    /// The glob the templates were loaded from, for the watcher to pick up new files
+    glob: Option<String>,
+    /// The escaping of each file extension, the other templates are not escaped
+    autoescape: HashMap<String, AutoEscape>,
+    /// The directories the templates which were not added are loaded from when first used
+    search_paths: Vec<SearchPath>,
+    /// The path of each template found through the search paths
+    loaded: Arc<Mutex<HashMap<String, PathBuf>>>,
+}
+
+#[derive(Debug, Clone)]
+struct SearchPath {
+    root: PathBuf,
+    /// Templates under a namespace are named as `@namespace/name.html`
+    namespace: Option<String>,
+}
+impl TemplatingEngine<'static> {
+    pub fn register_to_lua(lua: &mlua::Lua) -> mlua::Result<&'static str> {
+        lua.globals().set(
+            "astra_internal__new_templating_engine",
+            lua.create_async_function(
+                |lua, (dir, options): (Option<String>, Option<mlua::Table>)| async move {
+                    let engine = match &options {
+                        Some(options) => options.get::<Option<String>>("engine")?,
+                        None => None,
+                    };
+
+                    match engine.as_deref().unwrap_or("minijinja") {
+                        "minijinja" => lua.create_userdata(Self::new(dir).await?),
+                        "tera" => lua.create_userdata(TeraEngine::new(dir).await?),
+                        engine => Err(mlua::Error::runtime(format!(
+                            "Unknown templating engine {engine}, expected minijinja or tera"
+                        ))),
+                    }
+                },
+            )?,
+        )?;
+
+        Ok(include_str!("templates.lua"))
+    }
+
+    async fn new(dir: Option<String>) -> mlua::Result<Self> {
+        let mut engine = Self {
+            env: minijinja::Environment::new(),
+            templates: Vec::new(),
+            exclusions: Vec::new(),
+            glob: dir.clone(),
+            autoescape: HashMap::new(),
+            search_paths: Vec::new(),
+            loaded: Arc::new(Mutex::new(HashMap::new())),
+        };
+        filters::add_builtin_filters(&mut engine.env);
+        // keeps the template source on the errors for their snippets
+        engine.env.set_debug(true);
+        engine.set_autoescape(default_autoescape());
+
+        if let Some(dir) = dir {
+            // for includes of the files the glob does not match
+            engine.add_search_path(glob_base(&dir), None);
+
+            match parse_glob_pattern(&dir) {
+                Ok(matches) => {
+                    for (name, path) in matches {
+                        // get the file source
+                        match tokio::fs::read_to_string(path.clone()).await {
+                            Ok(source) => {
+                                engine.templates.push(Template {
+                                    name: name.clone(),
+                                    path: Some(path),
+                                    source: source.clone(),
+                                });
+
+                                if let Err(e) = engine.env.add_template_owned(name, source.clone())
+                                {
+                                    return Err(
+                                        TemplateError::new(&e, Some(&source)).into_lua_err()
+                                    );
+                                }
+                            }
+                            Err(e) => return Err(e.into_lua_err()),
+                        }
+                    }
+                }
+                Err(e) => return Err(e.into_lua_err()),
+            }
+        }
+
+        Ok(engine)
+    }
+}
+impl TemplatingEngine<'_> {
+    pub fn reload_templates(&mut self) -> mlua::Result<()> {
+        for i in self.templates.iter() {
+            let source = if let Some(source) = i
+                .path
+                .clone()
+                .and_then(|path| std::fs::read_to_string(path).ok())
+            {
+                source
+            } else {
+                i.source.clone()
+            };
+
+            if let Err(e) = self.env.add_template_owned(i.name.clone(), source.clone()) {
+                return Err(TemplateError::new(&e, Some(&source)).into_lua_err());
+            }
+        }
+
+        Ok(())
+    }
+}
+impl TemplatingEngine<'static> {
+    fn add_search_path(&mut self, root: PathBuf, namespace: Option<String>) {
+        self.search_paths.push(SearchPath { root, namespace });
+
+        let search_paths = self.search_paths.clone();
+        let loaded = self.loaded.clone();
+        self.env.set_loader(move |name| {
+            let (namespace, relative) =
+                match name.strip_prefix('@').and_then(|name| name.split_once('/')) {
+                    Some((namespace, relative)) => (Some(namespace), relative),
+                    None => (None, name),
+                };
+
+            for search_path in search_paths
+                .iter()
+                .filter(|search_path| search_path.namespace.as_deref() == namespace)
+            {
+                let Some(path) = join_template_path(&search_path.root, relative) else {
+                    return Ok(None);
+                };
+                if !path.is_file() {
+                    continue;
+                }
+
+                match std::fs::read_to_string(&path) {
+                    Ok(source) => {
+                        if let Ok(mut loaded) = loaded.lock() {
+                            loaded.insert(name.to_string(), path);
+                        }
+                        return Ok(Some(source));
+                    }
+                    Err(e) => {
+                        return Err(minijinja::Error::new(
+                            minijinja::ErrorKind::TemplateNotFound,
+                            format!("could not read {}", path.display()),
+                        )
+                        .with_source(e));
+                    }
+                }
+            }
+
+            Ok(None)
+        });
+    }
+
+    /// Adds or replaces a template read from a file, keeping the exclusions.
+    fn update_template_file(&mut self, name: String, path: String, source: String) {
+        if let Err(e) = self.env.add_template_owned(name.clone(), source.clone()) {
+            println!("{}", TemplateError::new(&e, Some(&source)));
+            return;
+        }
+
+        match self
+            .templates
+            .iter_mut()
+            .find(|template| template.name == name)
+        {
+            Some(template) => {
+                template.path = Some(path);
+                template.source = source;
+            }
+            None if !self.exclusions.iter().any(|i| i.as_ref() == name) => {
+                self.templates.push(Template {
+                    name,
+                    path: Some(path),
+                    source,
+                });
+            }
+            None => {}
+        }
+    }
+
+    /// Reads the changed files again, the ones of the templates which were added or match the
+    /// glob, while the ones found through the search paths are loaded again when next used.
+    fn reload_paths(&mut self, paths: Vec<PathBuf>) {
+        for path in paths {
+            // the file was removed or is being written, so the last source is kept
+            if !path.is_file() {
+                continue;
+            }
+
+            let loaded = self.loaded.lock().ok().and_then(|loaded| {
+                loaded
+                    .iter()
+                    .find(|(_, loaded_path)| watcher::is_same_file(loaded_path, &path))
+                    .map(|(name, _)| name.clone())
+            });
+            if let Some(name) = loaded {
+                self.env.remove_template(&name);
+                continue;
+            }
+
+            let added =
+                self.templates
+                    .iter()
+                    .find(|template| {
+                        template.path.as_ref().is_some_and(|template_path| {
+                            watcher::is_same_file(template_path, &path)
+                        })
+                    })
+                    .and_then(|template| Some((template.name.clone(), template.path.clone()?)))
+                    .or_else(|| {
+                        let name = watcher::glob_name(self.glob.as_deref()?, &path)?;
+                        Some((name, path.to_string_lossy().to_string()))
+                    });
+            let Some((name, template_path)) = added else {
+                continue;
+            };
+
+            match std::fs::read_to_string(&path) {
+                Ok(source) => self.update_template_file(name, template_path, source),
+                Err(e) => println!("TEMPLATING ERROR - Could not read {}: {e}", path.display()),
+            }
+        }
+    }
+
+    /// Reloads the changed template files as the filesystem reports them, and adds the new
+    /// files matching the glob.
+    fn watch(engine: mlua::AnyUserData, debounce: Option<u64>) -> mlua::Result<TaskHandler<()>> {
+        let roots = {
+            let this = engine.borrow::<Self>()?;
+            watcher::roots(
+                this.glob.as_deref(),
+                this.search_paths
+                    .iter()
+                    .map(|search_path| search_path.root.as_path()),
+                this.templates
+                    .iter()
+                    .filter_map(|template| template.path.as_deref()),
+            )
+        };
+
+        watcher::watch(
+            engine,
+            roots,
+            std::time::Duration::from_millis(debounce.unwrap_or(100)),
+            Self::reload_paths,
+        )
+    }
+}
+
+impl TemplatingEngine<'_> {
+    /// Merges the escaping of the extensions. As the escaping is decided when a template is
+    /// compiled, the templates already added are compiled again.
+    fn set_autoescape(&mut self, autoescape: HashMap<String, AutoEscape>) {
+        self.autoescape.extend(autoescape);
+
+        let autoescape = self.autoescape.clone();
+        self.env.set_auto_escape_callback(move |name| {
+            match strip_template_suffix(name).rsplit_once('.') {
+                Some((_, extension)) => autoescape
+                    .get(&extension.to_lowercase())
+                    .copied()
+                    .unwrap_or(AutoEscape::None),
+                None => AutoEscape::None,
+            }
+        });
+
+        let templates = self
+            .env
+            .templates()
+            .map(|(name, template)| (name.to_string(), template.source().to_string()))
+            .collect::<Vec<_>>();
+        for (name, source) in templates {
+            // the templates compiled before, so they still do
+            let _ = self.env.add_template_owned(name, source);
+        }
+    }
+}
+
+impl TemplatingEngine<'static> {
+    /// Renders a template, or only one of its blocks, into the body of a response. When
+    /// streaming, the template renders on a blocking task and is sent as it is written.
+    pub fn render_body(
+        &self,
+        lua: &mlua::Lua,
+        name: &str,
+        context: Option<mlua::Table>,
+        block: Option<String>,
+        stream: bool,
+    ) -> mlua::Result<axum::body::Body> {
+        let context = match context {
+            Some(context) => to_template_value(lua, mlua::Value::Table(context))?,
+            None => minijinja::Value::UNDEFINED,
+        };
+        let template = self
+            .env
+            .get_template(name)
+            .map_err(|e| TemplateError::new(&e, None).into_lua_err())?;
+
+        if !stream {
+            let result = match block {
+                Some(block) => template
+                    .eval_to_state(context)
+                    .and_then(|mut state| state.render_block(&block)),
+                None => template.render(context),
+            };
+
+            return match result {
+                Ok(result) => Ok(axum::body::Body::from(result)),
+                Err(e) => Err(TemplateError::new(&e, None).into_lua_err()),
+            };
+        }
+
+        let env = self.env.clone();
+        let name = name.to_string();
+        let (sender, receiver) = tokio::sync::mpsc::channel(16);
+        tokio::task::spawn_blocking(move || {
+            let mut writer = BodyWriter {
+                sender: sender.clone(),
+                buffer: Vec::new(),
+            };
+            let result = env.get_template(&name).and_then(|template| match block {
+                Some(block) => template
+                    .eval_to_state(context)?
+                    .render_block_to_write(&block, &mut writer),
+                None => template.render_to_write(context, &mut writer).map(|_| ()),
+            });
+
+            match result {
+                Ok(()) => {
+                    let _ = writer.send();
+                }
+                // the client is gone
+                Err(e) if e.kind() == minijinja::ErrorKind::WriteFailure => {}
+                Err(e) => {
+                    // the status is already sent, so the body is cut short instead
+                    let e = TemplateError::new(&e, None);
+                    println!("{e}");
+                    let _ = sender.blocking_send(Err(std::io::Error::other(e.message)));
+                }
+            }
+        });
+
+        Ok(axum::body::Body::from_stream(futures::stream::unfold(
+            receiver,
+            |mut receiver| async move { receiver.recv().await.map(|chunk| (chunk, receiver)) },
+        )))
+    }
+}
+
+/// Sends what the template writes to the response body in chunks.
+struct BodyWriter {
+    sender: tokio::sync::mpsc::Sender<std::io::Result<bytes::Bytes>>,
+    buffer: Vec<u8>,
+}
+impl BodyWriter {
+    const CHUNK_SIZE: usize = 8 * 1024;
+
+    fn send(&mut self) -> std::io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        let chunk = bytes::Bytes::from(std::mem::take(&mut self.buffer));
+        self.sender
+            .blocking_send(Ok(chunk))
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::BrokenPipe))
+    }
+}
+impl std::io::Write for BodyWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= Self::CHUNK_SIZE {
+            self.send()?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.send()
+    }
+}
+
+/// Renders a template of either engine into the body of a response.
+pub fn render_body(
+    lua: &mlua::Lua,
+    engine: &mlua::AnyUserData,
+    name: &str,
+    context: Option<mlua::Table>,
+    block: Option<String>,
+    stream: bool,
+) -> mlua::Result<axum::body::Body> {
+    if let Ok(engine) = engine.borrow::<TeraEngine>() {
+        return engine.render_body(lua, name, context, block, stream);
+    }
+
+    engine
+        .borrow::<TemplatingEngine>()?
+        .render_body(lua, name, context, block, stream)
+}
+
+/// The content type of the rendered template based on its extension, HTML by default.
+pub fn content_type(name: &str) -> String {
+    match mime_guess::from_path(strip_template_suffix(name)).first() {
+        Some(mime) if mime.type_() == mime_guess::mime::TEXT => format!("{mime}; charset=utf-8"),
+        Some(mime) => mime.to_string(),
+        None => "text/html; charset=utf-8".to_string(),
+    }
+}
+
+/// Removes the suffixes of Jinja files, as in `index.html.j2`.
+fn strip_template_suffix(name: &str) -> &str {
+    [".j2", ".jinja", ".jinja2"]
+        .into_iter()
+        .find_map(|suffix| name.strip_suffix(suffix))
+        .unwrap_or(name)
+}
+
+fn default_autoescape() -> HashMap<String, AutoEscape> {
+    let mut autoescape = HashMap::new();
+    for extension in ["html", "htm", "xhtml", "xml", "svg"] {
+        autoescape.insert(extension.to_string(), AutoEscape::Html);
+    }
+    for extension in ["json", "json5", "js", "yaml", "yml"] {
+        autoescape.insert(extension.to_string(), AutoEscape::Json);
+    }
+
+    autoescape
+}
+
+impl UserData for TemplatingEngine<'static> {
+    fn add_methods<M: mlua::UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method_mut(
+            "add_template",
+            |_, this, (name, template): (String, String)| match this
+                .env
+                .add_template_owned(name.clone(), template.clone())
+            {
+                Ok(()) => {
+                    this.templates.push(Template {
+                        name,
+                        path: None,
+                        source: template,
+                    });
+
+                    Ok(None)
+                }
+                Err(e) => Ok(Some(TemplateError::new(&e, Some(&template)))),
+            },
+        );
+        methods.add_method_mut(
+            "add_template_file",
+            |_, this, (name, path): (String, String)| match std::fs::read_to_string(&path) {
+                Ok(source) => match this.env.add_template_owned(name.clone(), source.clone()) {
+                    Ok(()) => {
+                        this.templates.push(Template {
+                            name,
+                            path: Some(path),
+                            source,
+                        });
+
+                        Ok(None)
+                    }
+                    Err(e) => Ok(Some(TemplateError::new(&e, Some(&source)))),
+                },
+                Err(e) => Err(e.into_lua_err()),
+            },
+        );
+        methods.add_method_mut("remove_template", |_, this, name: String| {
+            this.env.remove_template(&name.clone());
+
+            this.templates = this
+                .templates
+                .iter()
+                .filter(|template| template.name != name)
+                .cloned()
+                .collect::<Vec<_>>();
+
+            Ok(())
+        });
+        methods.add_method("get_template_names", |_, this, _: ()| {
+            Ok(this
+                .templates
+                .iter()
+                .map(|template| template.name.clone())
+                .collect::<Vec<_>>())
+        });
+        methods.add_method_mut("exclude_templates", |_, this, names: Vec<String>| {
+            for i in names {
+                this.templates = this
+                    .templates
+                    .iter()
+                    .filter(|template| template.name != i)
+                    .cloned()
+                    .collect::<Vec<_>>();
+                this.exclusions.push(i.into());
+            }
+
+            Ok(())
+        });
+        methods.add_method_mut("reload_templates", |_, this, _: ()| this.reload_templates());
+        methods.add_method_mut(
+            "add_search_path",
+            |_, this, (path, namespace): (String, Option<String>)| {
+                this.add_search_path(PathBuf::from(path), namespace);
+                Ok(())
+            },
+        );
+        methods.add_function(
+            "watch",
+            |_, (engine, debounce): (mlua::AnyUserData, Option<u64>)| Self::watch(engine, debounce),
+        );
+        methods.add_method_mut(
+            "add_function",
+            |_, this, (name, func): (String, mlua::Function)| {
+                let function = move |args: minijinja::Value|
+                                                                            -> Result<minijinja::Value, minijinja::Error> {
+                    match LUA.to_value(&args) {
+                        Ok(val) =>  match func.call::<mlua::Value>(val) {
+                            Ok(val) =>  match LUA.from_value::<minijinja::Value>(val) {
+                                Ok(val) => Ok(val),
+                                Err(e) =>
+                                    Err(minijinja::Error::new(UndefinedError,
+                                            format!("ERROR TEMPLATE FUNCTION - Could not convert the return type: {e}"))),
+                            },
+                            Err(e) =>
+                                Err(minijinja::Error::new(UndefinedError,
+                                        format!("ERROR TEMPLATE FUNCTION - Could not run the function: {e}"))),
+                        },
+                        Err(e) =>
+                            Err(minijinja::Error::new(UndefinedError,
+                                    format!("ERROR TEMPLATE FUNCTION - Could not convert arguments into Lua table: {e}"))),
+                    }
+                };
+
+                // have to leak the name
+                let static_name: &'static str = Box::leak(name.into_boxed_str());
+                this.env
+                    .add_function(static_name, function);
+                Ok(())
+            },
+        );
+
+        methods.add_method_mut(
+            "add_filter",
+            |_, this, (name, function): (String, mlua::Function)| {
+                this.env.add_filter(name, filters::lua_filter(function));
+                Ok(())
+            },
+        );
+        methods.add_method_mut(
+            "add_test",
+            |_, this, (name, function): (String, mlua::Function)| {
+                this.env.add_test(name, filters::lua_test(function));
+                Ok(())
+            },
+        );
+
+        methods.add_method_mut(
+            "set_autoescape",
+            |_, this, extensions: HashMap<String, mlua::Value>| {
+                let mut autoescape = HashMap::new();
+                for (extension, mode) in extensions {
+                    let mode = match mode {
+                        mlua::Value::Boolean(true) => AutoEscape::Html,
+                        mlua::Value::Boolean(false) | mlua::Value::Nil => AutoEscape::None,
+                        mode => match mode.to_string()?.as_str() {
+                            "html" => AutoEscape::Html,
+                            "json" => AutoEscape::Json,
+                            "none" => AutoEscape::None,
+                            mode => {
+                                return Err(mlua::Error::runtime(format!(
+                                    "Unknown escaping {mode} for {extension}, use html, json or none"
+                                )));
+                            }
+                        },
+                    };
+                    autoescape.insert(extension.trim_start_matches('.').to_lowercase(), mode);
+                }
+
+                this.set_autoescape(autoescape);
+                Ok(())
+            },
+        );
+
+        // returns the error instead of raising it, for the wrapper to raise it as a table
+        methods.add_method(
+            "render",
+            |lua, this, (name, context): (String, Option<mlua::Table>)| {
+                let context = match context {
+                    Some(context) => to_template_value(lua, mlua::Value::Table(context))?,
+                    None => minijinja::Value::UNDEFINED,
+                };
+
+                match this
+                    .env
+                    .get_template(&name)
+                    .and_then(|template| template.render(context))
+                {
+                    Ok(result) => Ok((Some(result), None)),
+                    Err(e) => Ok((None, Some(TemplateError::new(&e, None)))),
+                }
+            },
+        );
+    }
+}
+
+/// Converts a Lua value for the templates, turning datetime objects into RFC 3339 strings.
+fn to_template_value(lua: &mlua::Lua, value: mlua::Value) -> mlua::Result<minijinja::Value> {
+    let table = match value {
+        mlua::Value::Table(table) => table,
+        mlua::Value::UserData(datetime) if datetime.is::<LuaDateTime>() => {
+            return Ok(datetime.borrow::<LuaDateTime>()?.dt.to_rfc3339().into());
+        }
+        value => return lua.from_value::<minijinja::Value>(value),
+    };
+
+    // `Astra.datetime.new` wraps the userdata in a proxy table
+    if let Ok(mlua::Value::UserData(datetime)) = table.raw_get::<mlua::Value>("_obj")
+        && datetime.is::<LuaDateTime>()
+    {
+        return Ok(datetime.borrow::<LuaDateTime>()?.dt.to_rfc3339().into());
+    }
+
+    let length = table.raw_len();
+    if length > 0 && table.pairs::<mlua::Value, mlua::Value>().count() == length {
+        return table
+            .sequence_values::<mlua::Value>()
+            .map(|value| to_template_value(lua, value?))
+            .collect::<mlua::Result<Vec<_>>>()
+            .map(minijinja::Value::from);
+    }
+
+    let mut map = BTreeMap::new();
+    for pair in table.pairs::<mlua::Value, mlua::Value>() {
+        let (key, value) = pair?;
+        map.insert(key.to_string()?, to_template_value(lua, value)?);
+    }
+
+    Ok(minijinja::Value::from(map))
+}
+
+/// Joins the name of a template to the directory, refusing names which could leave it.
+fn join_template_path(root: &Path, name: &str) -> Option<PathBuf> {
+    let mut path = root.to_path_buf();
+    for segment in name.split('/') {
+        if segment.is_empty() || segment == "." {
+            continue;
+        }
+        if segment == ".." || segment.contains('\\') || Path::new(segment).has_root() {
+            return None;
+        }
+        path.push(segment);
+    }
+
+    Some(path)
+}
+
+/// The directory of the glob pattern before its first wildcard.
+fn glob_base(pattern: &str) -> PathBuf {
+    // Convert glob pattern to Path
+    let pattern_path = std::path::Path::new(pattern);
+
+    // Determine base directory by finding the part before the first wildcard
+    let mut base_path = std::path::PathBuf::new();
+    for component in pattern_path.components() {
+        if let std::path::Component::Normal(os_str) = component {
+            let part = os_str.to_string_lossy();
+            if part.contains('*') || part.contains('?') || part.contains('[') {
+                break;
+            }
+            base_path.push(part.as_ref());
+        } else {
+            base_path.push(component);
+        }
+    }
+
+    base_path
+}
+
+fn parse_glob_pattern(pattern: &str) -> Result<Vec<(String, String)>, mlua::Error> {
+    let base_path = glob_base(pattern);
+
+    // Perform the actual glob matching
+    let mut result = Vec::new();
+    match glob::glob(pattern) {
+        Ok(globs) => {
+            for entry in globs {
+                match entry {
+                    Ok(path) => {
+                        let full_path = path.to_string_lossy().to_string();
+                        if let Ok(relative) = path.strip_prefix(&base_path) {
+                            result.push((relative.to_string_lossy().to_string(), full_path));
+                        } else {
+                            // Fallback to full path if prefix can't be stripped
+                            result.push((full_path.clone(), full_path));
+                        }
+                    }
+                    Err(e) => return Err(e.into_lua_err()),
+                }
+            }
+            Ok(result)
+        }
+        Err(e) => Err(e.into_lua_err()),
+    }
+}
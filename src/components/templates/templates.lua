@@ -0,0 +1,173 @@
+---@meta
+
+--- Jinja2 templating engine
+---@class TemplateEngine
+---@field add_template fun(templates: TemplateEngine, name: string, template: string)
+---@field add_template_file fun(templates: TemplateEngine, name: string, path: string)
+---@field get_template_names fun(template: TemplateEngine): string[]
+---Excludes template files from being added to the server for rendering
+---@field exclude_templates fun(templates: TemplateEngine, names: string[])
+---@field reload_templates fun(templates: TemplateEngine) Refreshes the template code from the glob given at the start
+---Adds a directory the templates which were not added are loaded from when first used, such as the ones included or
+---extended. With a namespace, they are named with it as a prefix, like `@admin/layout.html`. The directory of the glob
+---given at the start is searched by default
+---@field add_search_path fun(templates: TemplateEngine, path: string, namespace?: string)
+---Reloads the changed template files as the filesystem reports them, and adds the new files matching the glob given at
+---the start. The changes are applied once the files were not written for the debounce in milliseconds, 100 by default.
+---Pages added with `add_to_server` are rendered on each request while watching
+---@field watch fun(templates: TemplateEngine, debounce?: number): TaskHandler
+---@field add_function fun(templates: TemplateEngine, name: string, function: template_function): any Add a function to the templates
+---Sets the escaping of the template file extensions, merged with the current ones. `true` or `"html"` escapes HTML,
+---`"json"` serializes the values into JSON, and `false` or `"none"` turns escaping off.
+---By default HTML, XML and SVG files escape HTML, and JSON, JavaScript and YAML files escape JSON
+---@field set_autoescape fun(templates: TemplateEngine, extensions: table<string, boolean|"html"|"json"|"none">)
+---Adds a filter, called with the filtered value followed by the arguments, and a table of the keyword arguments if any
+---@field add_filter fun(templates: TemplateEngine, name: string, filter: template_filter)
+---Adds a test for `is` checks, called the same way as the filters, which passes when the result is truthy
+---@field add_test fun(templates: TemplateEngine, name: string, test: template_filter)
+---Renders the given template into a string with the available context
+---@field render fun(templates: TemplateEngine, name: string, context?: table): string
+---@field add_to_server fun(templates: TemplateEngine, server: HTTPServer, context?: table) Adds the templates to the server
+---Adds the templates to the server in debugging manner, where the content refreshes on each request
+---@field add_to_server_debug fun(templates: TemplateEngine, server: HTTPServer, context?: table)
+
+---Raised when a template can not be compiled or rendered
+---@class TemplateError
+---@field kind string
+---@field name string|nil The template where the error happened
+---@field line number|nil
+---@field detail string|nil
+---@field snippet string|nil The lines around the error
+---@field message string Everything above formatted together
+
+---@diagnostic disable-next-line: duplicate-doc-alias
+---@alias template_function fun(args: table): any
+---@diagnostic disable-next-line: duplicate-doc-alias
+---@alias template_filter fun(value: any, ...: any): any
+
+
+---@class TemplateEngineOptions
+---The implementation behind the engine, `"minijinja"` by default. `"tera"` uses Tera, which
+---has its own filters and does not support search paths, JSON escaping or rendering single blocks
+---@field engine? "minijinja"|"tera"
+
+--- Returns a new templating engine
+---@param dir? string path to the directory, for example: `"templates/**/[!exclude.html]*.html"`
+---@param options? TemplateEngineOptions
+---@return TemplateEngine
+---@nodiscard
+function Astra.new_templating_engine(dir, options)
+	---@type TemplateEngine
+	---@diagnostic disable-next-line: undefined-global
+	local engine = astra_internal__new_templating_engine(dir, options)
+	---@type TemplateEngine
+	---@diagnostic disable-next-line: missing-fields
+	local TemplateEngineWrapper = { engine = engine }
+	local templates_re = Astra.regex([[(?:index)?\.(html|lua)$]])
+	local template_error_mt = {
+		__tostring = function(err)
+			return err.message
+		end,
+	}
+
+	-- raises the template errors as tables, from the caller of the method
+	local function raise_template_error(err)
+		if err then
+			error(setmetatable(err, template_error_mt), 3)
+		end
+	end
+
+	local function normalize_paths(path)
+		-- Ensure path starts with "/"
+		if path:sub(1, 1) ~= "/" then
+			path = "/" .. path
+		end
+
+		-- If empty, it's just the root
+		if path == "/" then
+			return { "/" }
+		end
+
+		-- Return both with and without trailing slash
+		if path:sub(-1) == "/" then
+			return { path, path:sub(1, -2) }
+		else
+			return { path, path .. "/" }
+		end
+	end
+
+	function TemplateEngineWrapper:add_to_server(server, context)
+		local names = self.engine:get_template_names()
+		for _, value in ipairs(names) do
+			local path = templates_re:replace(value, "")
+			local content = self:render(value, context)
+
+			for _, route in ipairs(normalize_paths(path)) do
+				server:get(route, function(_, response)
+					response:set_header("Content-Type", "text/html")
+					if self.watching then
+						return self:render(value, context)
+					end
+					return content
+				end)
+			end
+		end
+	end
+
+	function TemplateEngineWrapper:add_to_server_debug(server, context)
+		local names = self.engine:get_template_names()
+		for _, value in ipairs(names) do
+			local path = templates_re:replace(value, "")
+
+			for _, route in ipairs(normalize_paths(path)) do
+				server:get(route, function(_, response)
+					self.engine:reload_templates()
+					response:set_header("Content-Type", "text/html")
+					return self:render(value, context)
+				end)
+			end
+		end
+	end
+
+	function TemplateEngineWrapper:add_template(name, template)
+		raise_template_error(self.engine:add_template(name, template))
+	end
+
+	function TemplateEngineWrapper:add_template_file(name, path)
+		raise_template_error(self.engine:add_template_file(name, path))
+	end
+
+	function TemplateEngineWrapper:render(name, context)
+		local result, err = self.engine:render(name, context)
+		raise_template_error(err)
+		return result
+	end
+
+	function TemplateEngineWrapper:watch(interval)
+		self.watching = true
+		return self.engine:watch(interval)
+	end
+
+	local templating_methods = {
+		"get_template_names",
+		"exclude_templates",
+		"reload_templates",
+		"add_search_path",
+		"context_add",
+		"context_remove",
+		"context_get",
+		"add_function",
+		"add_filter",
+		"add_test",
+		"set_autoescape",
+	}
+
+	for _, method in ipairs(templating_methods) do
+		---@diagnostic disable-next-line: assign-type-mismatch
+		TemplateEngineWrapper[method] = function(self, ...)
+			return self.engine[method](self.engine, ...)
+		end
+	end
+
+	return TemplateEngineWrapper
+end
\ No newline at end of file
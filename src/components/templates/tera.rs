@@ -0,0 +1,515 @@
+use super::{
+    BodyWriter, Template, error::TemplateError, markdown, parse_glob_pattern, to_template_value,
+    watcher,
+};
+use crate::{LUA, components::global::TaskHandler};
+use mlua::{ExternalError, LuaSerdeExt, UserData};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::{Arc, LazyLock, Mutex},
+    time::Duration,
+};
+
+/// The suffixes given to Tera for autoescaping, which it keeps for as long as it runs. Each one
+/// is leaked once, however many engines are created or reloaded.
+static AUTOESCAPE_SUFFIXES: LazyLock<Mutex<HashSet<&'static str>>> =
+    LazyLock::new(Default::default);
+
+fn autoescape_suffix(extension: &str) -> &'static str {
+    let suffix = format!(".{extension}");
+    let mut suffixes = AUTOESCAPE_SUFFIXES
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    match suffixes.get(suffix.as_str()) {
+        Some(suffix) => suffix,
+        None => {
+            let suffix: &'static str = Box::leak(suffix.into_boxed_str());
+            suffixes.insert(suffix);
+            suffix
+        }
+    }
+}
+
+/// The templating engine backed by Tera, for templates written for Tera.
+///
+/// Tera needs every parent template when adding a child, so the templates are compiled
+/// together from `base`, which only holds the filters, functions and tests, whenever they
+/// change.
+#[derive(Debug, Clone)]
+pub struct TeraEngine {
+    pub tera: Arc<tera::Tera>,
+    base: tera::Tera,
+    /// Every template, including the excluded ones which others can still extend or include
+    templates: Vec<Template>,
+    exclusions: Vec<Arc<str>>,
+    glob: Option<String>,
+    autoescape: HashMap<String, bool>,
+}
+impl TeraEngine {
+    pub async fn new(dir: Option<String>) -> mlua::Result<Self> {
+        let mut base = tera::Tera::default();
+        base.register_filter("markdown", MarkdownFilter);
+
+        let mut engine = Self {
+            tera: Arc::new(base.clone()),
+            base,
+            templates: Vec::new(),
+            exclusions: Vec::new(),
+            glob: dir.clone(),
+            autoescape: HashMap::new(),
+        };
+        engine.set_autoescape(
+            ["html", "htm", "xhtml", "xml", "svg"]
+                .into_iter()
+                .map(|extension| (extension.to_string(), true))
+                .collect(),
+        );
+
+        if let Some(dir) = dir {
+            for (name, path) in parse_glob_pattern(&dir)? {
+                match tokio::fs::read_to_string(&path).await {
+                    Ok(source) => engine.templates.push(Template {
+                        name,
+                        path: Some(path),
+                        source,
+                    }),
+                    Err(e) => return Err(e.into_lua_err()),
+                }
+            }
+        }
+
+        match engine.compile() {
+            Some(e) => Err(e.into_lua_err()),
+            None => Ok(engine),
+        }
+    }
+
+    /// Returns the error instead of the compiled templates, which stay the previous ones.
+    fn compile(&mut self) -> Option<TemplateError> {
+        let mut tera = self.base.clone();
+        match tera.add_raw_templates(
+            self.templates
+                .iter()
+                .map(|template| (&template.name, &template.source)),
+        ) {
+            Ok(()) => {
+                self.tera = Arc::new(tera);
+                None
+            }
+            Err(e) => Some(TemplateError::from_tera(&e, None)),
+        }
+    }
+
+    /// Adds or replaces the template, keeping the previous templates when it can not compile.
+    fn add_template(&mut self, template: Template) -> Option<TemplateError> {
+        let previous = self.templates.clone();
+        self.templates.retain(|i| i.name != template.name);
+        let name = template.name.clone();
+        self.templates.push(template);
+
+        match self.compile() {
+            None => None,
+            Some(mut e) => {
+                self.templates = previous;
+                e.name.get_or_insert(name);
+                e.message = e.to_string();
+                Some(e)
+            }
+        }
+    }
+
+    /// Reads the template files again, adding the new files matching the glob.
+    fn reload_templates(&mut self) -> mlua::Result<()> {
+        if let Some(glob) = &self.glob {
+            for (name, path) in parse_glob_pattern(glob)? {
+                if !self.templates.iter().any(|template| template.name == name) {
+                    self.templates.push(Template {
+                        name,
+                        path: Some(path),
+                        source: String::new(),
+                    });
+                }
+            }
+        }
+
+        for template in self.templates.iter_mut() {
+            if let Some(source) = template
+                .path
+                .as_ref()
+                .and_then(|path| std::fs::read_to_string(path).ok())
+            {
+                template.source = source;
+            }
+        }
+        // files which could not be read yet
+        self.templates
+            .retain(|template| template.path.is_none() || !template.source.is_empty());
+
+        match self.compile() {
+            Some(e) => Err(e.into_lua_err()),
+            None => Ok(()),
+        }
+    }
+
+    fn set_autoescape(&mut self, autoescape: HashMap<String, bool>) {
+        self.autoescape.extend(autoescape);
+
+        let suffixes = self
+            .autoescape
+            .iter()
+            .filter(|(_, escape)| **escape)
+            .map(|(extension, _)| autoescape_suffix(extension))
+            .collect::<Vec<_>>();
+        self.base.autoescape_on(suffixes.clone());
+        Arc::make_mut(&mut self.tera).autoescape_on(suffixes);
+    }
+
+    fn context(lua: &mlua::Lua, context: Option<mlua::Table>) -> mlua::Result<tera::Context> {
+        let Some(context) = context else {
+            return Ok(tera::Context::new());
+        };
+
+        let context = serde_json::to_value(to_template_value(lua, mlua::Value::Table(context))?)
+            .map_err(|e| e.into_lua_err())?;
+        tera::Context::from_value(context).map_err(|e| {
+            mlua::Error::runtime(format!("Could not convert the template context: {e}"))
+        })
+    }
+
+    pub fn render_body(
+        &self,
+        lua: &mlua::Lua,
+        name: &str,
+        context: Option<mlua::Table>,
+        block: Option<String>,
+        stream: bool,
+    ) -> mlua::Result<axum::body::Body> {
+        if block.is_some() {
+            return Err(mlua::Error::runtime(
+                "Rendering a single block is only supported by the minijinja engine",
+            ));
+        }
+
+        let context = Self::context(lua, context)?;
+        if !stream {
+            return match self.tera.render(name, &context) {
+                Ok(result) => Ok(axum::body::Body::from(result)),
+                Err(e) => Err(TemplateError::from_tera(&e, Some(name)).into_lua_err()),
+            };
+        }
+
+        if let Err(e) = self.tera.get_template(name) {
+            return Err(TemplateError::from_tera(&e, Some(name)).into_lua_err());
+        }
+
+        let tera = self.tera.clone();
+        let name = name.to_string();
+        let (sender, receiver) = tokio::sync::mpsc::channel(16);
+        tokio::task::spawn_blocking(move || {
+            let mut writer = BodyWriter {
+                sender: sender.clone(),
+                buffer: Vec::new(),
+            };
+
+            match tera.render_to(&name, &context, &mut writer) {
+                Ok(()) => {
+                    let _ = writer.send();
+                }
+                // the client is gone
+                Err(e) if matches!(e.kind, tera::ErrorKind::Io(_)) => {}
+                Err(e) => {
+                    // the status is already sent, so the body is cut short instead
+                    let e = TemplateError::from_tera(&e, Some(&name));
+                    println!("{e}");
+                    let _ = sender.blocking_send(Err(std::io::Error::other(e.message)));
+                }
+            }
+        });
+
+        Ok(axum::body::Body::from_stream(futures::stream::unfold(
+            receiver,
+            |mut receiver| async move { receiver.recv().await.map(|chunk| (chunk, receiver)) },
+        )))
+    }
+
+    /// Reads the changed template files again and compiles the templates once, as the ones
+    /// extending or including them are compiled along with them.
+    fn reload_paths(&mut self, paths: Vec<PathBuf>) {
+        let mut changed = false;
+        for path in paths {
+            // the file was removed or is being written, so the last source is kept
+            if !path.is_file() {
+                continue;
+            }
+
+            let index = self.templates.iter().position(|template| {
+                template
+                    .path
+                    .as_ref()
+                    .is_some_and(|template_path| watcher::is_same_file(template_path, &path))
+            });
+            let index = match index {
+                Some(index) => index,
+                None => {
+                    let Some(name) = self
+                        .glob
+                        .as_deref()
+                        .and_then(|glob| watcher::glob_name(glob, &path))
+                    else {
+                        continue;
+                    };
+                    self.templates.push(Template {
+                        name,
+                        path: Some(path.to_string_lossy().to_string()),
+                        source: String::new(),
+                    });
+                    self.templates.len() - 1
+                }
+            };
+
+            match std::fs::read_to_string(&path) {
+                Ok(source) => {
+                    self.templates[index].source = source;
+                    changed = true;
+                }
+                Err(e) => println!("TEMPLATING ERROR - Could not read {}: {e}", path.display()),
+            }
+        }
+
+        if changed && let Some(e) = self.compile() {
+            println!("{e}");
+        }
+    }
+
+    /// Reloads the changed template files as the filesystem reports them, and adds the new
+    /// files matching the glob.
+    fn watch(engine: mlua::AnyUserData, debounce: Option<u64>) -> mlua::Result<TaskHandler<()>> {
+        let roots = {
+            let this = engine.borrow::<Self>()?;
+            watcher::roots(
+                this.glob.as_deref(),
+                [],
+                this.templates
+                    .iter()
+                    .filter_map(|template| template.path.as_deref()),
+            )
+        };
+
+        watcher::watch(
+            engine,
+            roots,
+            Duration::from_millis(debounce.unwrap_or(100)),
+            Self::reload_paths,
+        )
+    }
+}
+
+/// Renders Markdown like the filter of the minijinja engine, marked as safe for escaping.
+struct MarkdownFilter;
+impl tera::Filter for MarkdownFilter {
+    fn filter(
+        &self,
+        value: &tera::Value,
+        _: &HashMap<String, tera::Value>,
+    ) -> tera::Result<tera::Value> {
+        match value.as_str() {
+            Some(source) => Ok(tera::Value::String(markdown::to_html(source))),
+            None => Err(tera::Error::msg("The markdown filter expects a string")),
+        }
+    }
+
+    fn is_safe(&self) -> bool {
+        true
+    }
+}
+
+/// Calls a Lua function from a template, converting the arguments and the result through JSON.
+fn call_lua(function: &mlua::Function, args: mlua::MultiValue) -> tera::Result<tera::Value> {
+    let result = function
+        .call::<mlua::Value>(args)
+        .map_err(|e| tera::Error::msg(e.to_string()))?;
+
+    to_template_value(&LUA, result)
+        .map_err(|e| tera::Error::msg(e.to_string()))
+        .and_then(|value| serde_json::to_value(value).map_err(tera::Error::json))
+}
+
+fn to_lua(value: &tera::Value) -> tera::Result<mlua::Value> {
+    LUA.to_value(value)
+        .map_err(|e| tera::Error::msg(format!("Could not convert the arguments into Lua: {e}")))
+}
+
+impl UserData for TeraEngine {
+    fn add_methods<M: mlua::UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method_mut(
+            "add_template",
+            |_, this, (name, source): (String, String)| {
+                Ok(this.add_template(Template {
+                    name,
+                    path: None,
+                    source,
+                }))
+            },
+        );
+        methods.add_method_mut(
+            "add_template_file",
+            |_, this, (name, path): (String, String)| match std::fs::read_to_string(&path) {
+                Ok(source) => Ok(this.add_template(Template {
+                    name,
+                    path: Some(path),
+                    source,
+                })),
+                Err(e) => Err(e.into_lua_err()),
+            },
+        );
+        methods.add_method_mut("remove_template", |_, this, name: String| {
+            let previous = this.templates.clone();
+            this.templates.retain(|template| template.name != name);
+            match this.compile() {
+                Some(e) => {
+                    this.templates = previous;
+                    Err(e.into_lua_err())
+                }
+                None => Ok(()),
+            }
+        });
+        methods.add_method("get_template_names", |_, this, _: ()| {
+            Ok(this
+                .templates
+                .iter()
+                .filter(|template| !this.exclusions.iter().any(|i| **i == *template.name))
+                .map(|template| template.name.clone())
+                .collect::<Vec<_>>())
+        });
+        methods.add_method_mut("exclude_templates", |_, this, names: Vec<String>| {
+            this.exclusions
+                .extend(names.into_iter().map(|name| name.into()));
+            Ok(())
+        });
+        methods.add_method_mut("reload_templates", |_, this, _: ()| this.reload_templates());
+        methods.add_method("add_search_path", |_, _, _: mlua::MultiValue| {
+            Err::<(), _>(mlua::Error::runtime(
+                "Search paths are only supported by the minijinja engine, Tera only uses the templates of the glob",
+            ))
+        });
+        methods.add_function(
+            "watch",
+            |_, (engine, debounce): (mlua::AnyUserData, Option<u64>)| Self::watch(engine, debounce),
+        );
+        methods.add_method_mut(
+            "set_autoescape",
+            |_, this, extensions: HashMap<String, mlua::Value>| {
+                let mut autoescape = HashMap::new();
+                for (extension, mode) in extensions {
+                    let escape = match mode {
+                        mlua::Value::Boolean(escape) => escape,
+                        mlua::Value::Nil => false,
+                        mode => match mode.to_string()?.as_str() {
+                            "html" => true,
+                            "none" => false,
+                            mode => {
+                                return Err(mlua::Error::runtime(format!(
+                                    "Unknown escaping {mode} for {extension}, Tera only supports html or none"
+                                )));
+                            }
+                        },
+                    };
+                    autoescape.insert(extension.trim_start_matches('.').to_lowercase(), escape);
+                }
+
+                this.set_autoescape(autoescape);
+                Ok(())
+            },
+        );
+
+        // the function gets the keyword arguments as a table
+        methods.add_method_mut(
+            "add_function",
+            |_, this, (name, function): (String, mlua::Function)| {
+                let function = move |args: &HashMap<String, tera::Value>| {
+                    let args = args
+                        .iter()
+                        .map(|(key, value)| Ok((key.clone(), to_lua(value)?)))
+                        .collect::<tera::Result<HashMap<_, _>>>()?;
+                    let args = LUA
+                        .create_table_from(args)
+                        .map_err(|e| tera::Error::msg(e.to_string()))?;
+
+                    call_lua(
+                        &function,
+                        mlua::MultiValue::from_iter([mlua::Value::Table(args)]),
+                    )
+                };
+                this.base.register_function(&name, function.clone());
+                Arc::make_mut(&mut this.tera).register_function(&name, function);
+                Ok(())
+            },
+        );
+        // the filter gets the value, and the keyword arguments as a table if there are any
+        methods.add_method_mut(
+            "add_filter",
+            |_, this, (name, function): (String, mlua::Function)| {
+                let filter = move |value: &tera::Value, args: &HashMap<String, tera::Value>| {
+                    let mut lua_args = vec![to_lua(value)?];
+                    if !args.is_empty() {
+                        lua_args.push(to_lua(&tera::to_value(args)?)?);
+                    }
+
+                    call_lua(&function, mlua::MultiValue::from_iter(lua_args))
+                };
+                this.base.register_filter(&name, filter.clone());
+                Arc::make_mut(&mut this.tera).register_filter(&name, filter);
+                Ok(())
+            },
+        );
+        // the test gets the value followed by the arguments
+        methods.add_method_mut(
+            "add_test",
+            |_, this, (name, function): (String, mlua::Function)| {
+                let test = move |value: Option<&tera::Value>, args: &[tera::Value]| {
+                    let lua_args = std::iter::once(value.unwrap_or(&tera::Value::Null))
+                        .chain(args)
+                        .map(to_lua)
+                        .collect::<tera::Result<Vec<_>>>()?;
+
+                    let result = function
+                        .call::<mlua::Value>(mlua::MultiValue::from_iter(lua_args))
+                        .map_err(|e| tera::Error::msg(e.to_string()))?;
+                    Ok(!matches!(
+                        result,
+                        mlua::Value::Nil | mlua::Value::Boolean(false)
+                    ))
+                };
+                this.base.register_tester(&name, test.clone());
+                Arc::make_mut(&mut this.tera).register_tester(&name, test);
+                Ok(())
+            },
+        );
+
+        // returns the error instead of raising it, for the wrapper to raise it as a table
+        methods.add_method(
+            "render",
+            |lua, this, (name, context): (String, Option<mlua::Table>)| {
+                let context = Self::context(lua, context)?;
+                match this.tera.render(&name, &context) {
+                    Ok(result) => Ok((Some(result), None)),
+                    Err(e) => Ok((None, Some(TemplateError::from_tera(&e, Some(&name))))),
+                }
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leaks_each_autoescape_suffix_once() {
+        let first = autoescape_suffix("astra");
+        let second = autoescape_suffix("astra");
+        assert_eq!(first, ".astra");
+        assert!(std::ptr::eq(first, second));
+    }
+}
@@ -0,0 +1,114 @@
+//! Watches the template files through the notifications of the filesystem, so that only the
+//! changed files are read again.
+
+use super::glob_base;
+use crate::components::global::TaskHandler;
+use notify_debouncer_mini::{DebounceEventResult, new_debouncer, notify::RecursiveMode};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// The directories to watch: the ones of the glob and of the search paths with everything in
+/// them, and the ones of the other template files without their subdirectories.
+pub fn roots<'a>(
+    glob: Option<&str>,
+    directories: impl IntoIterator<Item = &'a Path>,
+    files: impl IntoIterator<Item = &'a str>,
+) -> Vec<(PathBuf, RecursiveMode)> {
+    let mut roots = Vec::<(PathBuf, RecursiveMode)>::new();
+    for directory in glob
+        .map(glob_base)
+        .into_iter()
+        .chain(directories.into_iter().map(Path::to_path_buf))
+    {
+        if let Ok(directory) = directory.canonicalize()
+            && directory.is_dir()
+            && !roots.iter().any(|(root, _)| directory.starts_with(root))
+        {
+            roots.retain(|(root, _)| !root.starts_with(&directory));
+            roots.push((directory, RecursiveMode::Recursive));
+        }
+    }
+
+    for file in files {
+        if let Some(directory) = Path::new(file)
+            .canonicalize()
+            .ok()
+            .and_then(|file| file.parent().map(Path::to_path_buf))
+            && !roots.iter().any(|(root, mode)| {
+                *root == directory
+                    || (*mode == RecursiveMode::Recursive && directory.starts_with(root))
+            })
+        {
+            roots.push((directory, RecursiveMode::NonRecursive));
+        }
+    }
+
+    roots
+}
+
+/// Watches the directories, giving the changed paths to `reload` once the writes settled for
+/// the debounce duration. Aborting the task stops the watching.
+pub fn watch<T: mlua::UserData + 'static>(
+    engine: mlua::AnyUserData,
+    roots: Vec<(PathBuf, RecursiveMode)>,
+    debounce: Duration,
+    reload: fn(&mut T, Vec<PathBuf>),
+) -> mlua::Result<TaskHandler<()>> {
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let mut debouncer = new_debouncer(debounce, move |result: DebounceEventResult| match result {
+        Ok(events) => {
+            let _ = sender.send(
+                events
+                    .into_iter()
+                    .map(|event| event.path)
+                    .collect::<Vec<_>>(),
+            );
+        }
+        Err(e) => println!("TEMPLATING ERROR - Could not watch the templates: {e}"),
+    })
+    .map_err(|e| mlua::Error::runtime(format!("Could not watch the templates: {e}")))?;
+
+    for (root, mode) in roots {
+        debouncer.watcher().watch(&root, mode).map_err(|e| {
+            mlua::Error::runtime(format!("Could not watch {}: {e}", root.display()))
+        })?;
+    }
+
+    Ok(TaskHandler {
+        handler: Some(tokio::spawn(async move {
+            // the watching stops once the debouncer is dropped along with the task
+            let _debouncer = debouncer;
+
+            while let Some(paths) = receiver.recv().await {
+                match engine.borrow_mut::<T>() {
+                    Ok(mut this) => reload(&mut this, paths),
+                    Err(e) => {
+                        println!("TEMPLATING ERROR - Could not reload the templates: {e}")
+                    }
+                }
+            }
+        })),
+    })
+}
+
+/// The name of the template when the changed file matches the glob, relative to its base as
+/// when the templates were loaded.
+pub fn glob_name(glob: &str, path: &Path) -> Option<String> {
+    let base = glob_base(glob);
+    let relative = path.strip_prefix(base.canonicalize().ok()?).ok()?;
+
+    glob::Pattern::new(glob)
+        .ok()?
+        .matches_path(&base.join(relative))
+        .then(|| relative.to_string_lossy().to_string())
+}
+
+/// Whether the template file is the changed one, as the notifications give canonical paths.
+pub fn is_same_file(template_path: impl AsRef<Path>, path: &Path) -> bool {
+    template_path
+        .as_ref()
+        .canonicalize()
+        .is_ok_and(|template_path| template_path == path)
+}
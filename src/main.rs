@@ -50,6 +50,25 @@ enum AstraCLI {
         /// Path to the export file.
         path: Option<String>,
     },
+    #[command(
+        arg_required_else_help = true,
+        about = "Applies, reverts or lists the SQL migrations of a database"
+    )]
+    Migrate {
+        /// Type of the database, either sqlite, postgres or mysql.
+        database_type: String,
+        /// URL of the database.
+        url: String,
+        /// What to do with the migrations.
+        #[arg(value_enum, default_value_t = commands::MigrateAction::Up)]
+        action: commands::MigrateAction,
+        /// Path to the folder containing the migration files.
+        #[arg(short, long, default_value = "migrations")]
+        source: String,
+        /// Version to revert down to. Defaults to reverting the latest migration.
+        #[arg(short, long, allow_negative_numbers = true)]
+        target: Option<i64>,
+    },
     #[command(about = "Updates to the latest version", alias = "update")]
     Upgrade {
         /// Custom user agent for requesting the updates
@@ -76,6 +95,20 @@ pub async fn main() {
             extra_args,
         } => commands::run_command(file_path, stdlib_path, extra_args).await,
         AstraCLI::ExportBundle { path } => commands::export_bundle_command(path).await,
+        AstraCLI::Migrate {
+            database_type,
+            url,
+            action,
+            source,
+            target,
+        } => {
+            if let Err(e) =
+                commands::migrate_command(database_type, url, action, source, target).await
+            {
+                eprintln!("Could not run the migrations: {e}");
+                std::process::exit(1);
+            }
+        }
         AstraCLI::Upgrade { user_agent } => {
             if let Err(e) = commands::upgrade_command(user_agent).await {
                 eprintln!("Could not update to the latest version: {e}");